@@ -1,90 +1,547 @@
-use crate::{error::Error, token::Token};
+use std::{
+    fmt::Write,
+    rc::Rc,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{
+    symbol::Symbol,
+    token::Token,
+    visitor::{ExprVisitor, StmtVisitor},
+};
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(tag = "kind"))]
 pub enum Expr {
+    Assign {
+        id: ExprId,
+        name: Token,
+        value: Box<Expr>,
+    },
     Binary {
         left: Box<Expr>,
         operator: Token,
         right: Box<Expr>,
     },
+    Call {
+        callee: Box<Expr>,
+        paren: Token,
+        arguments: Vec<Expr>,
+    },
+    Get {
+        object: Box<Expr>,
+        name: Token,
+    },
     Grouping {
         expression: Box<Expr>,
     },
     Literal {
         value: Literal,
     },
+    Logical {
+        left: Box<Expr>,
+        operator: Token,
+        right: Box<Expr>,
+    },
+    Set {
+        object: Box<Expr>,
+        name: Token,
+        value: Box<Expr>,
+    },
+    Super {
+        id: ExprId,
+        keyword: Token,
+        method: Token,
+    },
+    This {
+        id: ExprId,
+        keyword: Token,
+    },
     Unary {
         operator: Token,
         right: Box<Expr>,
     },
+    Variable {
+        id: ExprId,
+        name: Token,
+    },
 }
 
 impl Expr {
-    pub fn evaluate(&self) -> Result<String, Error> {
-        match self {
-            Expr::Binary {
-                left,
-                operator,
-                right,
-            } => todo!(),
-            Expr::Grouping { expression } => todo!(),
-            Expr::Literal { value } => todo!(),
-            Expr::Unary { operator, right } => todo!(),
-        }
+    pub fn print(&self) -> String {
+        Printer.visit_expr(self)
+    }
+}
+
+// Identifies a variable reference so the resolver can record its scope depth
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ExprId(usize);
+
+impl ExprId {
+    pub fn new() -> Self {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+        Self(NEXT_ID.fetch_add(1, Ordering::Relaxed))
     }
+}
+
+impl Default for ExprId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(tag = "kind"))]
+pub enum Stmt {
+    Block {
+        statements: Vec<Stmt>,
+    },
+    Class {
+        name: Token,
+        superclass: Option<Expr>,
+        methods: Vec<Rc<FunctionDecl>>,
+    },
+    Expression {
+        expression: Expr,
+    },
+    Function(Rc<FunctionDecl>),
+    If {
+        condition: Expr,
+        then_branch: Box<Stmt>,
+        else_branch: Option<Box<Stmt>>,
+    },
+    Print {
+        expression: Expr,
+    },
+    Return {
+        keyword: Token,
+        value: Option<Expr>,
+    },
+    Var {
+        name: Token,
+        initializer: Option<Expr>,
+    },
+    While {
+        condition: Expr,
+        body: Box<Stmt>,
+    },
+}
+
+impl Stmt {
+    pub fn print(&self) -> String {
+        Printer.visit_stmt(self)
+    }
+
+    // Renders a program as a Graphviz digraph, with one node per statement
+    // and expression and edges labelled by the role of the child
+    pub fn dot(statements: &[Stmt]) -> String {
+        let mut graph = Dot::default();
+        let root = graph.node("program");
+
+        statements
+            .iter()
+            .for_each(|statement| graph.statement(root, "body", statement));
+
+        graph.finish()
+    }
+}
+
+// Shared between the declaring statement and every closure created from it
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FunctionDecl {
+    pub name: Token,
+    pub params: Vec<Token>,
+    pub body: Vec<Stmt>,
+}
+
+impl FunctionDecl {
     pub fn print(&self) -> String {
-        match self {
-            Expr::Binary {
-                left,
-                operator,
-                right,
-            } => self.parenthesize(&operator.token_type.to_string(), vec![left, right]),
-            Expr::Grouping { expression } => self.parenthesize("group", vec![expression]),
-            Expr::Literal { value } => match value {
-                Literal::Stringy(literal) => literal.to_string(),
-                Literal::Number(literal) => literal.to_string(),
-                Literal::Bool(literal) => literal.to_string(),
-                Literal::None => "nil".to_string(),
-            },
-            Expr::Unary { operator, right } => {
-                self.parenthesize(&operator.token_type.to_string(), vec![right])
+        Printer.function(self)
+    }
+
+    fn head(&self) -> String {
+        let params: Vec<&str> = self.params.iter().map(|param| param.name()).collect();
+
+        format!("fun {}({})", self.name.name(), params.join(" "))
+    }
+}
+
+// Renders trees as s-expressions, e.g. `(* (- 123) (group 45.67))`
+struct Printer;
+
+impl Printer {
+    fn parenthesize(&mut self, name: &str, exprs: &[&Expr]) -> String {
+        sexpr(name, exprs.iter().map(|expr| self.visit_expr(expr)))
+    }
+
+    fn function(&mut self, declaration: &FunctionDecl) -> String {
+        sexpr(
+            &declaration.head(),
+            declaration.body.iter().map(|stmt| self.visit_stmt(stmt)),
+        )
+    }
+}
+
+impl ExprVisitor<String> for Printer {
+    fn visit_assign(&mut self, _: ExprId, name: &Token, value: &Expr) -> String {
+        self.parenthesize(&format!("= {}", name.name()), &[value])
+    }
+
+    fn visit_binary(&mut self, left: &Expr, operator: &Token, right: &Expr) -> String {
+        self.parenthesize(&operator.token_type.to_string(), &[left, right])
+    }
+
+    fn visit_call(&mut self, callee: &Expr, _: &Token, arguments: &[Expr]) -> String {
+        let mut exprs = vec![callee];
+        exprs.extend(arguments);
+
+        self.parenthesize("call", &exprs)
+    }
+
+    fn visit_get(&mut self, object: &Expr, name: &Token) -> String {
+        self.parenthesize(&format!(". {}", name.name()), &[object])
+    }
+
+    fn visit_grouping(&mut self, expression: &Expr) -> String {
+        self.parenthesize("group", &[expression])
+    }
+
+    fn visit_literal(&mut self, value: &Literal) -> String {
+        match value {
+            Literal::Stringy(literal) => format!("\"{}\"", literal),
+            Literal::Number(literal) => literal.to_string(),
+            Literal::Bool(literal) => literal.to_string(),
+            Literal::None => "nil".to_string(),
+        }
+    }
+
+    fn visit_logical(&mut self, left: &Expr, operator: &Token, right: &Expr) -> String {
+        self.parenthesize(&operator.token_type.to_string(), &[left, right])
+    }
+
+    fn visit_set(&mut self, object: &Expr, name: &Token, value: &Expr) -> String {
+        self.parenthesize(&format!(".= {}", name.name()), &[object, value])
+    }
+
+    fn visit_super(&mut self, _: ExprId, _: &Token, method: &Token) -> String {
+        format!("super.{}", method.name())
+    }
+
+    fn visit_this(&mut self, _: ExprId, _: &Token) -> String {
+        "this".to_string()
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: &Expr) -> String {
+        self.parenthesize(&operator.token_type.to_string(), &[right])
+    }
+
+    fn visit_variable(&mut self, _: ExprId, name: &Token) -> String {
+        name.name().to_string()
+    }
+}
+
+impl StmtVisitor<String> for Printer {
+    fn visit_child_expr(&mut self, expr: &Expr) -> String {
+        self.visit_expr(expr)
+    }
+
+    fn visit_block(&mut self, statements: &[Stmt]) -> String {
+        sexpr("block", statements.iter().map(|stmt| self.visit_stmt(stmt)))
+    }
+
+    fn visit_class(
+        &mut self,
+        name: &Token,
+        superclass: Option<&Expr>,
+        methods: &[Rc<FunctionDecl>],
+    ) -> String {
+        let head = match superclass {
+            Some(superclass) => {
+                format!("class {} < {}", name.name(), self.visit_expr(superclass))
             }
+            None => format!("class {}", name.name()),
+        };
+
+        sexpr(&head, methods.iter().map(|method| self.function(method)))
+    }
+
+    fn visit_expression(&mut self, expression: &Expr) -> String {
+        sexpr(";", [self.visit_expr(expression)])
+    }
+
+    fn visit_function(&mut self, declaration: &Rc<FunctionDecl>) -> String {
+        self.function(declaration)
+    }
+
+    fn visit_if(
+        &mut self,
+        condition: &Expr,
+        then_branch: &Stmt,
+        else_branch: Option<&Stmt>,
+    ) -> String {
+        let mut parts = vec![self.visit_expr(condition), self.visit_stmt(then_branch)];
+        parts.extend(else_branch.map(|else_branch| self.visit_stmt(else_branch)));
+
+        sexpr("if", parts)
+    }
+
+    fn visit_print(&mut self, expression: &Expr) -> String {
+        sexpr("print", [self.visit_expr(expression)])
+    }
+
+    fn visit_return(&mut self, _: &Token, value: Option<&Expr>) -> String {
+        sexpr("return", value.map(|value| self.visit_expr(value)))
+    }
+
+    fn visit_var(&mut self, name: &Token, initializer: Option<&Expr>) -> String {
+        let head = format!("var {}", name.name());
+
+        match initializer {
+            Some(initializer) => sexpr(&head, ["=".to_string(), self.visit_expr(initializer)]),
+            None => sexpr(&head, []),
         }
     }
 
-    fn parenthesize(&self, name: &str, exprs: Vec<&Expr>) -> String {
-        let mut buf = format!("({}", name);
+    fn visit_while(&mut self, condition: &Expr, body: &Stmt) -> String {
+        sexpr("while", [self.visit_expr(condition), self.visit_stmt(body)])
+    }
+}
+
+fn sexpr(head: &str, parts: impl IntoIterator<Item = String>) -> String {
+    let mut buf = format!("({}", head);
+
+    parts.into_iter().for_each(|part| {
+        buf.push(' ');
+        buf.push_str(&part);
+    });
+
+    buf.push(')');
+
+    buf
+}
+
+// Graphviz output under construction; nodes are numbered in creation order
+// and each visit returns the node it added
+#[derive(Default)]
+struct Dot {
+    buf: String,
+    nodes: usize,
+}
+
+impl Dot {
+    fn node(&mut self, label: &str) -> usize {
+        let node = self.nodes;
+        self.nodes += 1;
+
+        let label = label.replace('\\', "\\\\").replace('"', "\\\"");
+        let _ = writeln!(self.buf, "  n{} [label=\"{}\"];", node, label);
+
+        node
+    }
+
+    fn edge(&mut self, from: usize, to: usize, label: &str) {
+        let _ = writeln!(self.buf, "  n{} -> n{} [label=\"{}\"];", from, to, label);
+    }
+
+    // Every visit creates its own node before any of its children's, so the
+    // child's node is the next one to be numbered
+    fn child(&mut self, parent: usize, label: &str, expr: &Expr) {
+        let node = self.nodes;
+        self.visit_expr(expr);
+        self.edge(parent, node, label);
+    }
+
+    fn statement(&mut self, parent: usize, label: &str, stmt: &Stmt) {
+        let node = self.nodes;
+        self.visit_stmt(stmt);
+        self.edge(parent, node, label);
+    }
 
-        exprs.iter().for_each(|expr| {
-            buf.push(' ');
-            buf.push_str(expr.print().as_str())
+    fn function(&mut self, declaration: &FunctionDecl) -> usize {
+        let node = self.node(&declaration.head());
+
+        declaration
+            .body
+            .iter()
+            .for_each(|stmt| self.statement(node, "body", stmt));
+
+        node
+    }
+
+    // `ordering=out` keeps children left to right in source order
+    fn finish(self) -> String {
+        format!(
+            "digraph ast {{\n  ordering=out;\n  node [shape=box];\n{}}}\n",
+            self.buf
+        )
+    }
+}
+
+impl ExprVisitor<()> for Dot {
+    fn visit_assign(&mut self, _: ExprId, name: &Token, value: &Expr) {
+        let node = self.node(&format!("= {}", name.name()));
+        self.child(node, "value", value);
+    }
+
+    fn visit_binary(&mut self, left: &Expr, operator: &Token, right: &Expr) {
+        let node = self.node(&operator.token_type.to_string());
+        self.child(node, "left", left);
+        self.child(node, "right", right);
+    }
+
+    fn visit_call(&mut self, callee: &Expr, _: &Token, arguments: &[Expr]) {
+        let node = self.node("call");
+        self.child(node, "callee", callee);
+        arguments
+            .iter()
+            .enumerate()
+            .for_each(|(i, argument)| self.child(node, &format!("arg {}", i), argument));
+    }
+
+    fn visit_get(&mut self, object: &Expr, name: &Token) {
+        let node = self.node(&format!(". {}", name.name()));
+        self.child(node, "object", object);
+    }
+
+    fn visit_grouping(&mut self, expression: &Expr) {
+        let node = self.node("group");
+        self.child(node, "operand", expression);
+    }
+
+    fn visit_literal(&mut self, value: &Literal) {
+        self.node(&Printer.visit_literal(value));
+    }
+
+    fn visit_logical(&mut self, left: &Expr, operator: &Token, right: &Expr) {
+        self.visit_binary(left, operator, right)
+    }
+
+    fn visit_set(&mut self, object: &Expr, name: &Token, value: &Expr) {
+        let node = self.node(&format!(".= {}", name.name()));
+        self.child(node, "object", object);
+        self.child(node, "value", value);
+    }
+
+    fn visit_super(&mut self, _: ExprId, _: &Token, method: &Token) {
+        self.node(&format!("super.{}", method.name()));
+    }
+
+    fn visit_this(&mut self, _: ExprId, _: &Token) {
+        self.node("this");
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: &Expr) {
+        let node = self.node(&operator.token_type.to_string());
+        self.child(node, "operand", right);
+    }
+
+    fn visit_variable(&mut self, _: ExprId, name: &Token) {
+        self.node(name.name());
+    }
+}
+
+impl StmtVisitor<()> for Dot {
+    fn visit_child_expr(&mut self, expr: &Expr) {
+        self.visit_expr(expr)
+    }
+
+    fn visit_block(&mut self, statements: &[Stmt]) {
+        let node = self.node("block");
+        statements
+            .iter()
+            .for_each(|stmt| self.statement(node, "body", stmt));
+    }
+
+    fn visit_class(
+        &mut self,
+        name: &Token,
+        superclass: Option<&Expr>,
+        methods: &[Rc<FunctionDecl>],
+    ) {
+        let node = self.node(&format!("class {}", name.name()));
+
+        if let Some(superclass) = superclass {
+            self.child(node, "superclass", superclass);
+        }
+
+        methods.iter().for_each(|method| {
+            let child = self.function(method);
+            self.edge(node, child, "method");
         });
+    }
+
+    fn visit_expression(&mut self, expression: &Expr) {
+        let node = self.node(";");
+        self.child(node, "expression", expression);
+    }
+
+    fn visit_function(&mut self, declaration: &Rc<FunctionDecl>) {
+        self.function(declaration);
+    }
+
+    fn visit_if(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: Option<&Stmt>) {
+        let node = self.node("if");
+        self.child(node, "condition", condition);
+        self.statement(node, "then", then_branch);
+
+        if let Some(else_branch) = else_branch {
+            self.statement(node, "else", else_branch);
+        }
+    }
 
-        buf.push(')');
+    fn visit_print(&mut self, expression: &Expr) {
+        let node = self.node("print");
+        self.child(node, "expression", expression);
+    }
+
+    fn visit_return(&mut self, _: &Token, value: Option<&Expr>) {
+        let node = self.node("return");
+
+        if let Some(value) = value {
+            self.child(node, "value", value);
+        }
+    }
+
+    fn visit_var(&mut self, name: &Token, initializer: Option<&Expr>) {
+        let node = self.node(&format!("var {}", name.name()));
 
-        buf
+        if let Some(initializer) = initializer {
+            self.child(node, "initializer", initializer);
+        }
+    }
+
+    fn visit_while(&mut self, condition: &Expr, body: &Stmt) {
+        let node = self.node("while");
+        self.child(node, "condition", condition);
+        self.statement(node, "body", body);
     }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(tag = "kind", content = "value"))]
 pub enum Literal {
     Bool(bool),
     Number(f64),
-    Stringy(String),
+    Stringy(Symbol),
     None,
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::token::TokenType;
+    use crate::{parser::Parser, scanner::Scanner, token::TokenType};
 
     use super::*;
 
     #[test]
     fn check_print() {
-        let lop = Token::new(TokenType::Minus, 0);
-        let op = Token::new(TokenType::Minus, 0);
+        let lop = Token::synthetic(TokenType::Minus, 0);
+        let op = Token::synthetic(TokenType::Star, 0);
 
         let expr = Expr::Binary {
             left: Box::new(Expr::Unary {
@@ -103,4 +560,79 @@ mod tests {
 
         assert_eq!(expr.print(), "(* (- 123) (group 45.67))")
     }
+
+    #[test]
+    fn check_print_statements() {
+        let src = "class B < A { init(x) { this.x = x; } }
+            fun f() { return; }
+            var a;
+            if (a) print 1; else { a = 2; }
+            while (false) f();";
+        let tokens = Scanner::new(src.to_string()).scan_tokens().unwrap();
+        let statements = Parser::new(&tokens).parse().unwrap();
+
+        let printed: Vec<String> = statements.iter().map(Stmt::print).collect();
+
+        assert_eq!(
+            printed,
+            [
+                "(class B < A (fun init(x) (; (.= x this x))))",
+                "(fun f() (return))",
+                "(var a)",
+                "(if a (print 1) (block (; (= a 2))))",
+                "(while false (; (call f)))",
+            ]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn check_json() {
+        let tokens = Scanner::new("print -\"hi\";".to_string())
+            .scan_tokens()
+            .unwrap();
+        let statements = Parser::new(&tokens).parse().unwrap();
+
+        let json = serde_json::to_value(&statements).unwrap();
+        let unary = &json[0]["expression"];
+
+        assert_eq!(json[0]["kind"], "Print");
+        assert_eq!(unary["kind"], "Unary");
+        assert_eq!(unary["operator"]["token_type"]["type"], "Minus");
+        assert_eq!(unary["operator"]["span"]["start"], 6);
+        assert_eq!(unary["right"]["value"]["kind"], "Stringy");
+        assert_eq!(unary["right"]["value"]["value"], "hi");
+    }
+
+    #[test]
+    fn check_dot() {
+        let tokens = Scanner::new("while (a) print -\"b\" + 1;".to_string())
+            .scan_tokens()
+            .unwrap();
+        let statements = Parser::new(&tokens).parse().unwrap();
+
+        assert_eq!(
+            Stmt::dot(&statements),
+            r#"digraph ast {
+  ordering=out;
+  node [shape=box];
+  n0 [label="program"];
+  n1 [label="while"];
+  n2 [label="a"];
+  n1 -> n2 [label="condition"];
+  n3 [label="print"];
+  n4 [label="+"];
+  n5 [label="-"];
+  n6 [label="\"b\""];
+  n5 -> n6 [label="operand"];
+  n4 -> n5 [label="left"];
+  n7 [label="1"];
+  n4 -> n7 [label="right"];
+  n3 -> n4 [label="expression"];
+  n1 -> n3 [label="body"];
+  n0 -> n1 [label="body"];
+}
+"#
+        );
+    }
 }
@@ -0,0 +1,116 @@
+use std::{cell::RefCell, collections::HashMap, fmt::Display, rc::Rc};
+
+use crate::{
+    error::{Error::EvalErr, Result},
+    function::{Callable, Function},
+    interpreter::Interpreter,
+    symbol::Symbol,
+    token::Token,
+    value::Value,
+};
+
+#[derive(Debug)]
+pub struct Class {
+    pub name: String,
+    superclass: Option<Rc<Class>>,
+    methods: HashMap<Symbol, Rc<Function>>,
+}
+
+impl Class {
+    pub fn new(
+        name: &str,
+        superclass: Option<Rc<Class>>,
+        methods: HashMap<Symbol, Rc<Function>>,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            superclass,
+            methods,
+        }
+    }
+
+    pub fn find_method(&self, name: Symbol) -> Option<Rc<Function>> {
+        self.methods.get(&name).cloned().or_else(|| {
+            self.superclass
+                .as_ref()
+                .and_then(|superclass| superclass.find_method(name))
+        })
+    }
+}
+
+// Calling a class is implemented on the `Rc` so new instances can share it
+impl Callable for Rc<Class> {
+    fn arity(&self) -> usize {
+        self.find_method(Symbol::INIT)
+            .map_or(0, |initializer| initializer.arity())
+    }
+
+    fn call(&self, interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+        let instance = Rc::new(RefCell::new(Instance::new(self.clone())));
+
+        if let Some(initializer) = self.find_method(Symbol::INIT) {
+            initializer
+                .bind(instance.clone())
+                .call(interpreter, arguments)?;
+        }
+
+        Ok(Value::Instance(instance))
+    }
+}
+
+impl Display for Class {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+pub struct Instance {
+    class: Rc<Class>,
+    fields: HashMap<Symbol, Value>,
+}
+
+impl Instance {
+    pub fn new(class: Rc<Class>) -> Self {
+        Self {
+            class,
+            fields: HashMap::new(),
+        }
+    }
+
+    // Fields shadow methods; methods are bound to the instance on access
+    pub fn get(instance: &Rc<RefCell<Instance>>, name: &Token) -> Result<Value> {
+        if let Some(value) = instance.borrow().fields.get(&name.symbol()) {
+            return Ok(value.clone());
+        }
+
+        let method = instance.borrow().class.find_method(name.symbol());
+
+        match method {
+            Some(method) => Ok(Value::Function(Rc::new(method.bind(instance.clone())))),
+            None => Err(EvalErr {
+                line: name.line,
+                span: name.span,
+                code: "E0402",
+                message: format!("Undefined property '{}'", name.name()),
+                label: "no such property".to_string(),
+            }),
+        }
+    }
+
+    pub fn set(&mut self, name: &Token, value: Value) {
+        self.fields.insert(name.symbol(), value);
+    }
+}
+
+// Fields may point back at the instance itself, so keep this shallow
+impl std::fmt::Debug for Instance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Instance({})", self.class.name)
+    }
+}
+
+impl Display for Instance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} instance", self.class.name)
+    }
+}
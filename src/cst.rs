@@ -0,0 +1,572 @@
+use std::{fmt::Display, rc::Rc, vec::IntoIter};
+
+use crate::{
+    ast::{Expr, ExprId, FunctionDecl, Literal, Stmt},
+    parser::desugar_for,
+    token::{Span, Token, TokenType},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    Whitespace,
+    // A single `\n` or `\r\n`
+    Newline,
+    Comment,
+}
+
+// Source text between tokens that doesn't change the program's meaning
+#[derive(Debug, Clone)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+    pub span: Span,
+}
+
+// A token with the trivia around it. Trailing trivia runs up to the end of
+// the token's line; everything after that leads the next token, so the file's
+// last comments and whitespace lead the EOF token.
+#[derive(Debug, Clone)]
+pub struct CstToken {
+    pub token: Token,
+    pub text: String,
+    pub leading: Vec<Trivia>,
+    pub trailing: Vec<Trivia>,
+}
+
+impl Display for CstToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.leading
+            .iter()
+            .try_for_each(|trivia| write!(f, "{}", trivia.text))?;
+        write!(f, "{}", self.text)?;
+        self.trailing
+            .iter()
+            .try_for_each(|trivia| write!(f, "{}", trivia.text))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    Program,
+
+    // Declarations and statements. `FunDecl` is also used for methods,
+    // which have no `fun` keyword.
+    ClassDecl,
+    FunDecl,
+    Params,
+    VarDecl,
+    Block,
+    ExprStmt,
+    ForStmt,
+    IfStmt,
+    PrintStmt,
+    ReturnStmt,
+    WhileStmt,
+
+    // Expressions. `Assign` covers both variables and properties, which are
+    // only told apart when lowering.
+    Assign,
+    Binary,
+    Call,
+    Args,
+    Get,
+    Grouping,
+    Literal,
+    Logical,
+    Super,
+    This,
+    Unary,
+    Variable,
+}
+
+#[derive(Debug, Clone)]
+pub enum Element {
+    Node(Node),
+    Token(CstToken),
+}
+
+// Concrete syntax tree node. Children are kept in source order and every
+// token, including punctuation, is kept with its trivia, so printing the tree
+// gives back the source byte for byte.
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub children: Vec<Element>,
+}
+
+impl Display for Node {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.children.iter().try_for_each(|child| match child {
+            Element::Node(node) => write!(f, "{}", node),
+            Element::Token(token) => write!(f, "{}", token),
+        })
+    }
+}
+
+impl Node {
+    fn new(kind: NodeKind, children: Vec<Element>) -> Self {
+        Self { kind, children }
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &Node> {
+        self.children.iter().filter_map(|child| match child {
+            Element::Node(node) => Some(node),
+            Element::Token(_) => None,
+        })
+    }
+
+    pub fn tokens(&self) -> impl Iterator<Item = &Token> {
+        self.children.iter().filter_map(|child| match child {
+            Element::Node(_) => None,
+            Element::Token(token) => Some(&token.token),
+        })
+    }
+
+    // The parser fixes the shape of each kind of node, so lowering can pick
+    // children out by position
+    fn node(&self, index: usize) -> &Node {
+        self.nodes()
+            .nth(index)
+            .unwrap_or_else(|| panic!("{:?} node is missing child {}", self.kind, index))
+    }
+
+    fn token(&self, index: usize) -> Token {
+        *self
+            .tokens()
+            .nth(index)
+            .unwrap_or_else(|| panic!("{:?} node is missing token {}", self.kind, index))
+    }
+
+    // Lowers a program or block to the statements inside it. `Parser` only
+    // hands out trees for valid programs, so lowering can't fail.
+    pub fn lower(&self) -> Vec<Stmt> {
+        self.nodes().map(Node::lower_stmt).collect()
+    }
+
+    pub fn lower_stmt(&self) -> Stmt {
+        match self.kind {
+            NodeKind::ClassDecl => Stmt::Class {
+                name: self.token(1),
+                superclass: self
+                    .nodes()
+                    .find(|node| node.kind == NodeKind::Variable)
+                    .map(Node::lower_expr),
+                methods: self
+                    .nodes()
+                    .filter(|node| node.kind == NodeKind::FunDecl)
+                    .map(Node::lower_function)
+                    .collect(),
+            },
+            NodeKind::FunDecl => Stmt::Function(self.lower_function()),
+            NodeKind::VarDecl => Stmt::Var {
+                name: self.token(1),
+                initializer: self.nodes().next().map(Node::lower_expr),
+            },
+            NodeKind::Block => Stmt::Block {
+                statements: self.lower(),
+            },
+            NodeKind::ExprStmt => Stmt::Expression {
+                expression: self.node(0).lower_expr(),
+            },
+            NodeKind::ForStmt => self.lower_for(),
+            NodeKind::IfStmt => Stmt::If {
+                condition: self.node(0).lower_expr(),
+                then_branch: Box::new(self.node(1).lower_stmt()),
+                else_branch: self.nodes().nth(2).map(|node| Box::new(node.lower_stmt())),
+            },
+            NodeKind::PrintStmt => Stmt::Print {
+                expression: self.node(0).lower_expr(),
+            },
+            NodeKind::ReturnStmt => Stmt::Return {
+                keyword: self.token(0),
+                value: self.nodes().next().map(Node::lower_expr),
+            },
+            NodeKind::WhileStmt => Stmt::While {
+                condition: self.node(0).lower_expr(),
+                body: Box::new(self.node(1).lower_stmt()),
+            },
+            kind => unreachable!("{:?} is not a statement", kind),
+        }
+    }
+
+    // Each clause of the header may be missing, so they're found by walking
+    // past the punctuation that ends them
+    fn lower_for(&self) -> Stmt {
+        fn clause<'a>(children: &mut impl Iterator<Item = &'a Element>) -> Option<&'a Node> {
+            match children.next() {
+                Some(Element::Node(node)) => Some(node),
+                _ => None,
+            }
+        }
+
+        let mut children = self.children[2..].iter();
+
+        let initializer = clause(&mut children).map(Node::lower_stmt);
+
+        let condition = clause(&mut children);
+        if condition.is_some() {
+            children.next();
+        }
+
+        let increment = clause(&mut children);
+        if increment.is_some() {
+            children.next();
+        }
+
+        let body = clause(&mut children).expect("A for loop always has a body");
+
+        desugar_for(
+            initializer,
+            condition.map(Node::lower_expr),
+            increment.map(Node::lower_expr),
+            body.lower_stmt(),
+        )
+    }
+
+    fn lower_function(&self) -> Rc<FunctionDecl> {
+        let name = *self
+            .tokens()
+            .find(|token| token.token_type == TokenType::IDENTIFIER)
+            .expect("A function always has a name");
+
+        Rc::new(FunctionDecl {
+            name,
+            params: self
+                .node(0)
+                .tokens()
+                .filter(|token| token.token_type == TokenType::IDENTIFIER)
+                .copied()
+                .collect(),
+            body: self.node(1).lower(),
+        })
+    }
+
+    pub fn lower_expr(&self) -> Expr {
+        match self.kind {
+            NodeKind::Assign => {
+                let value = Box::new(self.node(1).lower_expr());
+
+                match self.node(0).lower_expr() {
+                    Expr::Variable { name, .. } => Expr::Assign {
+                        id: ExprId::new(),
+                        name,
+                        value,
+                    },
+                    Expr::Get { object, name } => Expr::Set {
+                        object,
+                        name,
+                        value,
+                    },
+                    _ => unreachable!("The parser rejects invalid assignment targets"),
+                }
+            }
+            NodeKind::Binary => Expr::Binary {
+                left: Box::new(self.node(0).lower_expr()),
+                operator: self.token(0),
+                right: Box::new(self.node(1).lower_expr()),
+            },
+            NodeKind::Call => {
+                let arguments = self.node(1);
+
+                Expr::Call {
+                    callee: Box::new(self.node(0).lower_expr()),
+                    paren: *arguments.tokens().last().expect("Arguments end in ')'"),
+                    arguments: arguments.nodes().map(Node::lower_expr).collect(),
+                }
+            }
+            NodeKind::Get => Expr::Get {
+                object: Box::new(self.node(0).lower_expr()),
+                name: self.token(1),
+            },
+            NodeKind::Grouping => Expr::Grouping {
+                expression: Box::new(self.node(0).lower_expr()),
+            },
+            NodeKind::Literal => {
+                let value = match self.token(0).token_type {
+                    TokenType::False => Literal::Bool(false),
+                    TokenType::True => Literal::Bool(true),
+                    TokenType::Nil => Literal::None,
+                    TokenType::Number(num) => Literal::Number(num),
+                    TokenType::Stringy(value) => Literal::Stringy(value),
+                    token_type => unreachable!("{} is not a literal", token_type),
+                };
+
+                Expr::Literal { value }
+            }
+            NodeKind::Logical => Expr::Logical {
+                left: Box::new(self.node(0).lower_expr()),
+                operator: self.token(0),
+                right: Box::new(self.node(1).lower_expr()),
+            },
+            NodeKind::Super => Expr::Super {
+                id: ExprId::new(),
+                keyword: self.token(0),
+                method: self.token(2),
+            },
+            NodeKind::This => Expr::This {
+                id: ExprId::new(),
+                keyword: self.token(0),
+            },
+            NodeKind::Unary => Expr::Unary {
+                operator: self.token(0),
+                right: Box::new(self.node(0).lower_expr()),
+            },
+            NodeKind::Variable => Expr::Variable {
+                id: ExprId::new(),
+                name: self.token(0),
+            },
+            kind => unreachable!("{:?} is not an expression", kind),
+        }
+    }
+}
+
+// Assembles a CST as `Parser` goes, from the tokens the parser consumes
+// (with their trivia attached) and the nodes it opens and closes around them.
+pub struct CstBuilder {
+    tokens: IntoIter<CstToken>,
+    // The program node and every node still open inside it, innermost last
+    stack: Vec<Node>,
+}
+
+impl CstBuilder {
+    pub fn new(src: &str, tokens: &[Token]) -> Self {
+        Self {
+            tokens: attach_trivia(src, tokens).into_iter(),
+            stack: vec![Node::new(NodeKind::Program, vec![])],
+        }
+    }
+
+    fn open(&mut self) -> &mut Node {
+        self.stack
+            .last_mut()
+            .expect("The program node is never finished")
+    }
+
+    // Marks where a node may later start, for when the parser only knows
+    // what it has read once it's past the first child, as with `a + b`
+    pub fn checkpoint(&self) -> usize {
+        self.stack.last().map_or(0, |node| node.children.len())
+    }
+
+    pub fn start(&mut self, kind: NodeKind) {
+        self.stack.push(Node::new(kind, vec![]));
+    }
+
+    // Opens a node that takes over everything added since `checkpoint`
+    pub fn start_at(&mut self, checkpoint: usize, kind: NodeKind) {
+        let children = self.open().children.split_off(checkpoint);
+        self.stack.push(Node::new(kind, children));
+    }
+
+    // Adds the parser's next token to the open node
+    pub fn token(&mut self) {
+        let token = self.tokens.next().expect("The parser never reads past EOF");
+        self.open().children.push(Element::Token(token));
+    }
+
+    pub fn finish(&mut self) {
+        let node = self
+            .stack
+            .pop()
+            .expect("Finished more nodes than were started");
+        self.open().children.push(Element::Node(node));
+    }
+
+    // Closes the program with the EOF token, which the parser never consumes
+    pub fn build(mut self) -> Node {
+        let tokens: Vec<CstToken> = self.tokens.by_ref().collect();
+        self.open()
+            .children
+            .extend(tokens.into_iter().map(Element::Token));
+
+        assert_eq!(self.stack.len(), 1, "Every node should have been finished");
+        self.stack.remove(0)
+    }
+}
+
+// Turns comment tokens and the gaps between tokens into trivia, attached to
+// the neighbouring tokens
+fn attach_trivia(src: &str, tokens: &[Token]) -> Vec<CstToken> {
+    let mut attached: Vec<CstToken> = vec![];
+    let mut pending = vec![];
+    let mut offset = 0;
+
+    for token in tokens {
+        pending.extend(whitespace(src, offset, token.span.start));
+        offset = token.span.end;
+
+        if token.token_type == TokenType::Comment {
+            pending.push(Trivia {
+                kind: TriviaKind::Comment,
+                text: token.lexeme(src).to_string(),
+                span: token.span,
+            });
+            continue;
+        }
+
+        // Anything before the first line break trails the previous token
+        if let Some(previous) = attached.last_mut() {
+            let end = pending
+                .iter()
+                .position(|trivia| trivia.kind == TriviaKind::Newline)
+                .unwrap_or(pending.len());
+            previous.trailing = pending.drain(..end).collect();
+        }
+
+        attached.push(CstToken {
+            token: *token,
+            text: token.lexeme(src).to_string(),
+            leading: std::mem::take(&mut pending),
+            trailing: vec![],
+        });
+    }
+
+    attached
+}
+
+// Splits the whitespace between two tokens into runs and line breaks
+fn whitespace(src: &str, start: usize, end: usize) -> Vec<Trivia> {
+    let mut trivia = vec![];
+    let mut run_start = start;
+    let mut offset = start;
+
+    let mut push = |kind, start: usize, end: usize| {
+        if start < end {
+            trivia.push(Trivia {
+                kind,
+                text: src[start..end].to_string(),
+                span: Span::new(start, end),
+            });
+        }
+    };
+
+    while offset < end {
+        let newline = match &src[offset..end] {
+            rest if rest.starts_with("\r\n") => 2,
+            rest if rest.starts_with('\n') => 1,
+            _ => 0,
+        };
+
+        if newline > 0 {
+            push(TriviaKind::Whitespace, run_start, offset);
+            push(TriviaKind::Newline, offset, offset + newline);
+            offset += newline;
+            run_start = offset;
+        } else {
+            // Only ASCII whitespace appears between tokens
+            offset += 1;
+        }
+    }
+
+    push(TriviaKind::Whitespace, run_start, end);
+
+    trivia
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{error::Error, parser::Parser, scanner::Scanner};
+
+    use super::*;
+
+    fn cst(src: &str) -> Node {
+        let tokens = Scanner::new(src.to_string()).scan_tokens().unwrap();
+        Parser::with_cst(src, &tokens).parse_cst().unwrap()
+    }
+
+    #[test]
+    fn check_round_trip() {
+        let sources = [
+            "",
+            "  \n\n",
+            "print 1;",
+            "// only a comment",
+            "var s = \"multi\nline ✓\";  \t// trailing \n\n\n// leading\r\nprint s ;\r\n",
+            "class A < B {\n  init() { super.init(); this.x = -1; }\n}\nA().x;",
+            "fun f(a, b) { for (var i = 0; i < a; i = i + 1) { if (i) return; else b(); } }",
+            "for (;;) while (!true) { print (1 + 2) * 3 or nil and false; }\n   ",
+        ];
+
+        sources
+            .iter()
+            .for_each(|src| assert_eq!(cst(src).to_string(), *src));
+    }
+
+    #[test]
+    fn check_trivia() {
+        let program = cst("var a; // one\n\n// two\nprint a;");
+
+        let Element::Token(semicolon) = &program.node(0).children[2] else {
+            panic!("Expected the ';' ending the declaration");
+        };
+        let texts: Vec<&str> = semicolon
+            .trailing
+            .iter()
+            .map(|trivia| trivia.text.as_str())
+            .collect();
+        assert_eq!(texts, [" ", "// one"]);
+
+        let Element::Token(print) = &program.node(1).children[0] else {
+            panic!("Expected the 'print' keyword");
+        };
+        let kinds: Vec<TriviaKind> = print.leading.iter().map(|trivia| trivia.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                TriviaKind::Newline,
+                TriviaKind::Newline,
+                TriviaKind::Comment,
+                TriviaKind::Newline,
+            ]
+        );
+    }
+
+    #[test]
+    fn check_lower() {
+        let src = "class B < A { init(x) { this.x = x; super.init(); } }
+            fun f(a, b) { return a(b).c; }
+            for (var i = 0; i < 3; i = i + 1) print -i;
+            for (;;) {}
+            if (!nil and true) print \"y\"; else { var z = 1 or 2; }
+            while (false) f(1, 2);";
+
+        let tokens = Scanner::new(src.to_string()).scan_tokens().unwrap();
+        let parsed: Vec<String> = Parser::new(&tokens)
+            .parse()
+            .unwrap()
+            .iter()
+            .map(Stmt::print)
+            .collect();
+        let lowered: Vec<String> = cst(src).lower().iter().map(Stmt::print).collect();
+
+        assert_eq!(lowered, parsed);
+    }
+
+    #[test]
+    fn check_errors() {
+        let errors = |src: &str| -> Vec<String> {
+            let tokens = Scanner::new(src.to_string()).scan_tokens().unwrap();
+            let Err(errors) = Parser::with_cst(src, &tokens).parse_cst() else {
+                panic!("Expected a syntax error in {:?}", src);
+            };
+
+            errors.iter().map(Error::to_string).collect()
+        };
+
+        assert_eq!(
+            errors("print (1;"),
+            ["[line 1] Parse error: Expect ')' after expression"]
+        );
+        assert_eq!(
+            errors("1 = 2;"),
+            ["[line 1] Parse error: Invalid assignment target"]
+        );
+
+        // The tree comes from the same parser, so it gets the same limits
+        let arguments = vec!["nil"; 256].join(", ");
+        assert_eq!(
+            errors(&format!("f({});", arguments)),
+            ["[line 1] Parse error: Can't have more than 255 arguments"]
+        );
+    }
+}
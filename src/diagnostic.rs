@@ -0,0 +1,264 @@
+use std::fmt::{Display, Write};
+
+use crate::token::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    // Primary labels mark the fault itself and are underlined with carets;
+    // secondary ones point at related code and use dashes
+    pub primary: bool,
+}
+
+// A message about a range of source, rendered rustc-style as the offending
+// lines with each label underlined beneath them
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, code, message)
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, code, message)
+    }
+
+    fn new(severity: Severity, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            code,
+            message: message.into(),
+            labels: vec![],
+            notes: vec![],
+            help: None,
+        }
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary: true,
+        });
+        self
+    }
+
+    pub fn with_secondary_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary: false,
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    // `name` is shown in the location header, e.g. the script's path
+    pub fn render(&self, name: &str, src: &str) -> String {
+        let mut buf = format!("{}[{}]: {}\n", self.severity, self.code, self.message);
+
+        // Labels pointing outside `src` can't be shown, so they're left out
+        // rather than risk slicing out of bounds
+        let mut labels: Vec<(Position, &Label)> = self
+            .labels
+            .iter()
+            .filter(|label| label.span.within(src))
+            .map(|label| (Position::of(src, label.span.start), label))
+            .collect();
+        labels.sort_by_key(|(position, label)| (position.line, !label.primary));
+
+        let gutter = labels
+            .iter()
+            .map(|(position, _)| position.line.to_string().len())
+            .max()
+            .unwrap_or(0);
+        let pad = " ".repeat(gutter);
+
+        if let Some((position, _)) = labels.iter().find(|(_, label)| label.primary) {
+            let _ = writeln!(
+                buf,
+                "{}--> {}:{}:{}",
+                pad, name, position.line, position.column
+            );
+        }
+
+        if !labels.is_empty() {
+            let _ = writeln!(buf, "{} |", pad);
+        }
+
+        let mut previous_line = None;
+
+        for (position, label) in &labels {
+            let text = src[position.line_start..]
+                .lines()
+                .next()
+                .unwrap_or_default();
+
+            if previous_line != Some(position.line) {
+                if previous_line.is_some_and(|line| position.line > line + 1) {
+                    let _ = writeln!(buf, "{}...", pad);
+                }
+
+                let source_line = format!("{:>gutter$} | {}", position.line, text);
+                let _ = writeln!(buf, "{}", source_line.trim_end());
+                previous_line = Some(position.line);
+            }
+
+            // Spans running past the end of the line are cut off there
+            let offset = label.span.start - position.line_start;
+            let end = label
+                .span
+                .end
+                .clamp(label.span.start, position.line_start + text.len());
+            let width = src[label.span.start..end].chars().count().max(1);
+            let marker = if label.primary { "^" } else { "-" };
+
+            let underline = format!(
+                "{} | {}{} {}",
+                pad,
+                " ".repeat(text[..offset.min(text.len())].chars().count()),
+                marker.repeat(width),
+                label.message
+            );
+            let _ = writeln!(buf, "{}", underline.trim_end());
+        }
+
+        if !labels.is_empty() && (!self.notes.is_empty() || self.help.is_some()) {
+            let _ = writeln!(buf, "{} |", pad);
+        }
+
+        self.notes.iter().for_each(|note| {
+            let _ = writeln!(buf, "{} = note: {}", pad, note);
+        });
+
+        if let Some(help) = &self.help {
+            let _ = writeln!(buf, "{} = help: {}", pad, help);
+        }
+
+        buf
+    }
+}
+
+// Line and column (counted in chars, from 1) of a byte offset
+struct Position {
+    line: usize,
+    column: usize,
+    line_start: usize,
+}
+
+impl Position {
+    fn of(src: &str, offset: usize) -> Self {
+        let offset = offset.min(src.len());
+        let before = &src[..offset];
+        let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+
+        Self {
+            line: before.matches('\n').count() + 1,
+            column: src[line_start..offset].chars().count() + 1,
+            line_start,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_render() {
+        let src = "var a = 1;\nvar a = \"ü\" + nil;\n";
+        let diagnostic = Diagnostic::error("E0405", "Operands must be two numbers or two strings")
+            .with_label(Span::new(24, 25), "can't add these")
+            .with_secondary_label(Span::new(19, 23), "this is a string")
+            .with_secondary_label(Span::new(4, 5), "first declared here")
+            .with_note("'+' doesn't convert its operands")
+            .with_help("use a string literal instead of nil");
+
+        assert_eq!(
+            diagnostic.render("test.lox", src),
+            "error[E0405]: Operands must be two numbers or two strings
+ --> test.lox:2:13
+  |
+1 | var a = 1;
+  |     - first declared here
+2 | var a = \"ü\" + nil;
+  |             ^ can't add these
+  |         --- this is a string
+  |
+  = note: '+' doesn't convert its operands
+  = help: use a string literal instead of nil
+"
+        );
+    }
+
+    #[test]
+    fn check_render_without_labels() {
+        let diagnostic = Diagnostic::error("E0502", "Failed to write output");
+
+        assert_eq!(
+            diagnostic.render("test.lox", ""),
+            "error[E0502]: Failed to write output\n"
+        );
+    }
+
+    #[test]
+    fn check_render_warning() {
+        let diagnostic = Diagnostic::warning("W0001", "Unused variable 'a'")
+            .with_label(Span::new(4, 5), "never read");
+
+        assert_eq!(
+            diagnostic.render("test.lox", "var a = 1;\n"),
+            "warning[W0001]: Unused variable 'a'
+ --> test.lox:1:5
+  |
+1 | var a = 1;
+  |     ^ never read
+"
+        );
+    }
+
+    #[test]
+    fn check_render_out_of_range() {
+        // A span from some other source, as the REPL once produced
+        let diagnostic = Diagnostic::error("E0405", "Operands must be two numbers or two strings")
+            .with_label(Span::new(17, 24), "can't add these");
+
+        assert_eq!(
+            diagnostic.render("<repl>", "f();\n"),
+            "error[E0405]: Operands must be two numbers or two strings\n"
+        );
+    }
+}
@@ -0,0 +1,97 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use crate::{
+    error::{
+        Error::{self, EvalErr},
+        Result,
+    },
+    symbol::Symbol,
+    token::Token,
+    value::Value,
+};
+
+#[derive(Debug, Default)]
+pub struct Environment {
+    values: HashMap<Symbol, Value>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_enclosing(enclosing: Rc<RefCell<Environment>>) -> Self {
+        Self {
+            values: HashMap::new(),
+            enclosing: Some(enclosing),
+        }
+    }
+
+    pub fn define(&mut self, name: Symbol, value: Value) {
+        self.values.insert(name, value);
+    }
+
+    pub fn get(&self, name: &Token) -> Result<Value> {
+        if let Some(value) = self.values.get(&name.symbol()) {
+            return Ok(value.clone());
+        }
+
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow().get(name),
+            None => Err(Self::undefined(name)),
+        }
+    }
+
+    pub fn assign(&mut self, name: &Token, value: Value) -> Result<()> {
+        if let Some(slot) = self.values.get_mut(&name.symbol()) {
+            *slot = value;
+            return Ok(());
+        }
+
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow_mut().assign(name, value),
+            None => Err(Self::undefined(name)),
+        }
+    }
+
+    // `distance` comes from the resolver, which guarantees the variable lives there
+    pub fn get_at(&self, distance: usize, name: &Token) -> Result<Value> {
+        if distance == 0 {
+            return self
+                .values
+                .get(&name.symbol())
+                .cloned()
+                .ok_or_else(|| Self::undefined(name));
+        }
+
+        self.enclosing
+            .as_ref()
+            .expect("Resolved scope depth exceeds environment chain")
+            .borrow()
+            .get_at(distance - 1, name)
+    }
+
+    pub fn assign_at(&mut self, distance: usize, name: &Token, value: Value) -> Result<()> {
+        if distance == 0 {
+            self.values.insert(name.symbol(), value);
+            return Ok(());
+        }
+
+        self.enclosing
+            .as_ref()
+            .expect("Resolved scope depth exceeds environment chain")
+            .borrow_mut()
+            .assign_at(distance - 1, name, value)
+    }
+
+    fn undefined(name: &Token) -> Error {
+        EvalErr {
+            line: name.line,
+            span: name.span,
+            code: "E0401",
+            message: format!("Undefined variable '{}'", name.name()),
+            label: "not defined".to_string(),
+        }
+    }
+}
@@ -1,9 +1,141 @@
+use std::fmt::Display;
+
+use crate::{diagnostic::Diagnostic, token::Span};
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug)]
 #[allow(clippy::enum_variant_names)]
 pub enum Error {
-    ParseErr(String),
-    EvalErr(String),
-    GeneralErr(String),
+    ParseErr {
+        line: usize,
+        span: Span,
+        code: &'static str,
+        message: String,
+        // Text underlining `span`, saying what is wrong there
+        label: String,
+    },
+    ResolveErr {
+        line: usize,
+        span: Span,
+        code: &'static str,
+        message: String,
+        label: String,
+    },
+    CompileErr {
+        line: usize,
+        span: Span,
+        code: &'static str,
+        message: String,
+        label: String,
+    },
+    EvalErr {
+        line: usize,
+        span: Span,
+        code: &'static str,
+        message: String,
+        label: String,
+    },
+    GeneralErr {
+        code: &'static str,
+        message: String,
+    },
+}
+
+impl Error {
+    // Codes are grouped by phase: E00xx lexing, E01xx parsing, E02xx
+    // resolving, E03xx compiling, E04xx runtime and E05xx everything else.
+    // Each kind of error has its own code within its phase.
+    pub fn diagnostic(&self) -> Diagnostic {
+        let diagnostic = match self {
+            Error::ParseErr {
+                span,
+                code,
+                message,
+                label,
+                ..
+            }
+            | Error::ResolveErr {
+                span,
+                code,
+                message,
+                label,
+                ..
+            }
+            | Error::CompileErr {
+                span,
+                code,
+                message,
+                label,
+                ..
+            }
+            | Error::EvalErr {
+                span,
+                code,
+                message,
+                label,
+                ..
+            } => Diagnostic::error(code, message).with_label(*span, label),
+            Error::GeneralErr { code, message } => Diagnostic::error(code, message),
+        };
+
+        match note(diagnostic.code) {
+            Some(note) => diagnostic.with_note(note),
+            None => diagnostic,
+        }
+    }
+}
+
+// Limits behind errors that only make sense once you know they exist
+fn note(code: &str) -> Option<&'static str> {
+    match code {
+        "E0104" => Some("a function can have at most 255 parameters"),
+        "E0105" | "E0307" => Some("a call can pass at most 255 arguments"),
+        "E0302" => Some("a function can capture at most 256 variables"),
+        "E0303" => Some("a function can have at most 256 local variables in scope"),
+        "E0411" => Some("calls can nest at most 1024 deep"),
+        _ => None,
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::ParseErr { line, message, .. } => {
+                write!(f, "[line {}] Parse error: {}", line, message)
+            }
+            Error::ResolveErr { line, message, .. } => {
+                write!(f, "[line {}] Resolve error: {}", line, message)
+            }
+            Error::CompileErr { line, message, .. } => {
+                write!(f, "[line {}] Compile error: {}", line, message)
+            }
+            Error::EvalErr { line, message, .. } => {
+                write!(f, "[line {}] Runtime error: {}", line, message)
+            }
+            Error::GeneralErr { message, .. } => write!(f, "Error: {}", message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_diagnostic() {
+        let err = Error::EvalErr {
+            line: 1,
+            span: Span::new(4, 5),
+            code: "E0411",
+            message: "Stack overflow".to_string(),
+            label: "too many nested calls".to_string(),
+        };
+        let diagnostic = err.diagnostic();
+
+        assert_eq!(diagnostic.code, "E0411");
+        assert_eq!(diagnostic.labels[0].message, "too many nested calls");
+        assert_eq!(diagnostic.notes, ["calls can nest at most 1024 deep"]);
+        assert_eq!(err.to_string(), "[line 1] Runtime error: Stack overflow");
+    }
 }
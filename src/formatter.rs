@@ -0,0 +1,276 @@
+use crate::{
+    cst::{CstToken, Element, Node, TriviaKind},
+    token::{Token, TokenType},
+};
+
+const INDENT: &str = "    ";
+
+// A token or comment to write out, with the text it came from
+#[derive(Clone, Copy)]
+enum Piece<'a> {
+    Code(Token, &'a str),
+    Comment(&'a str),
+}
+
+impl Piece<'_> {
+    fn token_type(&self) -> TokenType {
+        match self {
+            Piece::Code(token, _) => token.token_type,
+            Piece::Comment(_) => TokenType::Comment,
+        }
+    }
+}
+
+// Re-emits a program's CST in canonical layout: one statement per line,
+// four-space indents, opening braces on the same line and single spaces
+// around binary operators. Comments and line breaks come from the trivia, so
+// comments come through untouched.
+pub struct Formatter<'a> {
+    program: &'a Node,
+    buf: String,
+    indent: usize,
+    // Open parens, so the `;`s of a `for` header stay on one line
+    parens: usize,
+    // Line breaks in the source since the last piece written
+    breaks: usize,
+    // Last piece written, the last token that wasn't a comment, and whether
+    // that was a prefix `-` or `!`
+    previous: Option<Piece<'a>>,
+    last_code: Option<Token>,
+    unary: bool,
+    // The last line broke mid-statement, so the next one is indented further
+    continued: bool,
+}
+
+impl<'a> Formatter<'a> {
+    pub fn new(program: &'a Node) -> Self {
+        Self {
+            program,
+            buf: String::new(),
+            indent: 0,
+            parens: 0,
+            breaks: 0,
+            previous: None,
+            last_code: None,
+            unary: false,
+            continued: false,
+        }
+    }
+
+    pub fn format(mut self) -> String {
+        self.node(self.program);
+
+        if !self.buf.is_empty() {
+            self.buf.push('\n');
+        }
+
+        self.buf
+    }
+
+    fn node(&mut self, node: &'a Node) {
+        node.children.iter().for_each(|child| match child {
+            Element::Node(node) => self.node(node),
+            Element::Token(token) => self.token(token),
+        });
+    }
+
+    fn token(&mut self, token: &'a CstToken) {
+        token.leading.iter().for_each(|trivia| match trivia.kind {
+            TriviaKind::Whitespace => {}
+            TriviaKind::Newline => self.breaks += 1,
+            TriviaKind::Comment => self.piece(Piece::Comment(&trivia.text)),
+        });
+
+        if token.token.token_type != TokenType::Eof {
+            self.piece(Piece::Code(token.token, &token.text));
+        }
+
+        token.trailing.iter().for_each(|trivia| {
+            if trivia.kind == TriviaKind::Comment {
+                self.piece(Piece::Comment(&trivia.text));
+            }
+        });
+    }
+
+    fn piece(&mut self, piece: Piece<'a>) {
+        match self.previous {
+            Some(previous) if self.breaks_between(previous, piece) => self.newline(previous, piece),
+            Some(previous) if self.spaced_between(previous, piece) => self.buf.push(' '),
+            _ => {}
+        }
+
+        self.write(piece);
+        self.breaks = 0;
+    }
+
+    fn write(&mut self, piece: Piece<'a>) {
+        match piece.token_type() {
+            TokenType::LeftParen => self.parens += 1,
+            TokenType::RightParen => self.parens = self.parens.saturating_sub(1),
+            TokenType::RightBrace => self.indent = self.indent.saturating_sub(1),
+            _ => {}
+        }
+
+        if self.buf.is_empty() || self.buf.ends_with('\n') {
+            let depth = self.indent + self.continued as usize;
+            self.buf.push_str(&INDENT.repeat(depth));
+        }
+
+        // Comments keep their text, less any trailing whitespace
+        match piece {
+            Piece::Code(_, text) => self.buf.push_str(text),
+            Piece::Comment(text) => self.buf.push_str(text.trim_end()),
+        }
+
+        if let Piece::Code(token, _) = piece {
+            if token.token_type == TokenType::LeftBrace {
+                self.indent += 1;
+            }
+
+            self.unary = match token.token_type {
+                TokenType::Bang => true,
+                TokenType::Minus => !self.last_code.as_ref().is_some_and(ends_operand),
+                _ => false,
+            };
+            self.last_code = Some(token);
+        }
+
+        self.previous = Some(piece);
+    }
+
+    fn breaks_between(&self, previous: Piece, piece: Piece) -> bool {
+        match (previous.token_type(), piece.token_type()) {
+            (TokenType::Comment, _) => true,
+            // Trailing comments stay on the line they annotate
+            (_, TokenType::Comment) => self.breaks > 0,
+            (TokenType::LeftBrace, TokenType::RightBrace) => false,
+            (TokenType::LeftBrace, _) | (_, TokenType::RightBrace) => true,
+            (TokenType::RightBrace, TokenType::Else) => false,
+            (TokenType::RightBrace, _) => true,
+            (TokenType::Semicolon, _) => self.parens == 0,
+            _ => false,
+        }
+    }
+
+    fn newline(&mut self, previous: Piece, piece: Piece) {
+        self.buf.push('\n');
+
+        // Keep at most one of the blank lines separating statements
+        let blank = self.breaks > 1
+            && previous.token_type() != TokenType::LeftBrace
+            && piece.token_type() != TokenType::RightBrace;
+
+        if blank {
+            self.buf.push('\n');
+        }
+
+        // Unless the next line closes a block, it's a continuation if the
+        // code so far stops partway through a statement
+        self.continued = piece.token_type() != TokenType::RightBrace
+            && self.last_code.is_some_and(|code| match code.token_type {
+                TokenType::Semicolon => self.parens > 0,
+                TokenType::LeftBrace | TokenType::RightBrace => false,
+                _ => true,
+            });
+    }
+
+    fn spaced_between(&self, previous: Piece, piece: Piece) -> bool {
+        if self.unary && previous.token_type() != TokenType::Comment {
+            return false;
+        }
+
+        match (previous.token_type(), piece.token_type()) {
+            (TokenType::LeftParen | TokenType::Dot, _) => false,
+            (
+                _,
+                TokenType::RightParen | TokenType::Semicolon | TokenType::Comma | TokenType::Dot,
+            ) => false,
+            (TokenType::LeftBrace, TokenType::RightBrace) => false,
+            // Calls and parameter lists hug their callee
+            (_, TokenType::LeftParen) => {
+                !matches!(previous, Piece::Code(token, _) if ends_operand(&token))
+            }
+            _ => true,
+        }
+    }
+}
+
+// Tokens that can end an operand, after which `-` is subtraction
+fn ends_operand(token: &Token) -> bool {
+    matches!(
+        token.token_type,
+        TokenType::Identifier(_)
+            | TokenType::Stringy(_)
+            | TokenType::Number(_)
+            | TokenType::True
+            | TokenType::False
+            | TokenType::Nil
+            | TokenType::This
+            | TokenType::RightParen
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ast::Stmt, parser::Parser, scanner::Scanner};
+
+    use super::*;
+
+    fn format(src: &str) -> String {
+        let tokens = Scanner::new(src.to_string()).scan_tokens().unwrap();
+        let program = Parser::with_cst(src, &tokens).parse_cst().unwrap();
+
+        Formatter::new(&program).format()
+    }
+
+    fn print(src: &str) -> Vec<String> {
+        let tokens = Scanner::new(src.to_string()).scan_tokens().unwrap();
+        let statements = Parser::new(&tokens).parse().unwrap();
+
+        statements.iter().map(Stmt::print).collect()
+    }
+
+    #[test]
+    fn check_format() {
+        let src = "fun f(a,b){if(a<-b)return a;else{return b-1;}}\n\n\n\
+                   class A<B{init(){this.x=!super.m(1);}}\
+                   for(var i=0;i<3;i=i+1){}";
+        let formatted = format(src);
+
+        assert_eq!(
+            formatted,
+            "fun f(a, b) {
+    if (a < -b) return a;
+    else {
+        return b - 1;
+    }
+}
+
+class A < B {
+    init() {
+        this.x = !super.m(1);
+    }
+}
+for (var i = 0; i < 3; i = i + 1) {}
+"
+        );
+        assert_eq!(format(&formatted), formatted);
+        assert_eq!(print(&formatted), print(src));
+    }
+
+    #[test]
+    fn check_comments() {
+        let src = "// header\nvar a = 1 +   // one  \n2;\n{ // open\n// inside\n}";
+
+        assert_eq!(
+            format(src),
+            "// header
+var a = 1 + // one
+    2;
+{ // open
+    // inside
+}
+"
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use std::{cell::RefCell, fmt::Display, rc::Rc};
+
+use crate::{
+    ast::FunctionDecl,
+    class::Instance,
+    environment::Environment,
+    error::Result,
+    interpreter::Interpreter,
+    symbol::Symbol,
+    token::{Token, TokenType},
+    value::Value,
+};
+
+pub trait Callable {
+    fn arity(&self) -> usize;
+    fn call(&self, interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value>;
+}
+
+pub struct Function {
+    declaration: Rc<FunctionDecl>,
+    closure: Rc<RefCell<Environment>>,
+    is_initializer: bool,
+}
+
+impl Function {
+    pub fn new(
+        declaration: Rc<FunctionDecl>,
+        closure: Rc<RefCell<Environment>>,
+        is_initializer: bool,
+    ) -> Self {
+        Self {
+            declaration,
+            closure,
+            is_initializer,
+        }
+    }
+
+    // Wraps the closure in a scope where `this` refers to `instance`
+    pub fn bind(&self, instance: Rc<RefCell<Instance>>) -> Function {
+        let mut environment = Environment::with_enclosing(self.closure.clone());
+        environment.define(Symbol::THIS, Value::Instance(instance));
+
+        Function::new(
+            self.declaration.clone(),
+            Rc::new(RefCell::new(environment)),
+            self.is_initializer,
+        )
+    }
+
+    fn this(&self) -> Result<Value> {
+        let this = Token::synthetic(TokenType::This, self.declaration.name.line);
+
+        self.closure.borrow().get_at(0, &this)
+    }
+}
+
+impl Callable for Function {
+    fn arity(&self) -> usize {
+        self.declaration.params.len()
+    }
+
+    fn call(&self, interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+        let mut environment = Environment::with_enclosing(self.closure.clone());
+
+        self.declaration
+            .params
+            .iter()
+            .zip(arguments)
+            .for_each(|(param, argument)| environment.define(param.symbol(), argument));
+
+        let value = interpreter
+            .execute_block(&self.declaration.body, Rc::new(RefCell::new(environment)))?
+            .break_value();
+
+        // Initializers always hand back the instance, even on a bare `return;`
+        match value {
+            _ if self.is_initializer => self.this(),
+            Some(value) => Ok(value),
+            None => Ok(Value::Nil),
+        }
+    }
+}
+
+// Closures can reach themselves through their environment, so keep this shallow
+impl std::fmt::Debug for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Function({})", self.declaration.name.name())
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<fn {}>", self.declaration.name.name())
+    }
+}
+
+#[derive(Debug)]
+pub struct NativeFunction {
+    pub name: &'static str,
+    pub arity: usize,
+    pub function: fn(&mut Interpreter, &[Value]) -> Result<Value>,
+}
+
+impl Callable for NativeFunction {
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn call(&self, interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+        (self.function)(interpreter, &arguments)
+    }
+}
+
+impl Display for NativeFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn>")
+    }
+}
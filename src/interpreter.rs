@@ -0,0 +1,816 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io::{stdout, Write},
+    ops::ControlFlow,
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    ast::{Expr, ExprId, FunctionDecl, Literal, Stmt},
+    class::{Class, Instance},
+    environment::Environment,
+    error::{
+        Error::{EvalErr, GeneralErr},
+        Result,
+    },
+    function::{Callable, Function, NativeFunction},
+    symbol::Symbol,
+    token::{Token, TokenType},
+    value::Value,
+    visitor::{ExprVisitor, StmtVisitor},
+};
+
+// Matches the VM's frame limit, so runaway recursion is reported as a Lox
+// error instead of overflowing the native stack
+const MAX_CALL_DEPTH: usize = 1024;
+
+pub struct Interpreter {
+    globals: Rc<RefCell<Environment>>,
+    environment: Rc<RefCell<Environment>>,
+    locals: HashMap<ExprId, usize>,
+    call_depth: usize,
+    args: Vec<String>,
+    output: Box<dyn Write>,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::with_output(Box::new(stdout()))
+    }
+
+    pub fn with_output(output: Box<dyn Write>) -> Self {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+
+        let mut interpreter = Self {
+            environment: globals.clone(),
+            globals,
+            locals: HashMap::new(),
+            call_depth: 0,
+            args: vec![],
+            output,
+        };
+
+        interpreter.define_native("clock", 0, |_, _| {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_err(|err| GeneralErr {
+                    code: "E0501",
+                    message: format!("Clock went backwards: {}", err),
+                })?;
+
+            Ok(Value::Number(now.as_secs_f64()))
+        });
+        interpreter.define_native("argc", 0, |interpreter, _| {
+            Ok(Value::Number(interpreter.args.len() as f64))
+        });
+        interpreter.define_native("arg", 1, |interpreter, arguments| {
+            let arg = match arguments[0] {
+                Value::Number(index) if index >= 0.0 && index.fract() == 0.0 => {
+                    interpreter.args.get(index as usize)
+                }
+                _ => None,
+            };
+
+            Ok(arg.map_or(Value::Nil, |arg| Value::Stringy(Symbol::intern(arg))))
+        });
+
+        interpreter
+    }
+
+    // Arguments given to the script after its path, exposed through `arg(i)`
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.args = args;
+    }
+
+    fn define_native(
+        &mut self,
+        name: &'static str,
+        arity: usize,
+        function: fn(&mut Interpreter, &[Value]) -> Result<Value>,
+    ) {
+        let native = NativeFunction {
+            name,
+            arity,
+            function,
+        };
+
+        self.globals
+            .borrow_mut()
+            .define(Symbol::intern(name), Value::NativeFunction(Rc::new(native)));
+    }
+
+    pub fn resolve(&mut self, locals: HashMap<ExprId, usize>) {
+        self.locals.extend(locals);
+    }
+
+    pub fn interpret(&mut self, statements: &[Stmt]) -> Result<()> {
+        for statement in statements {
+            self.execute(statement)?;
+        }
+
+        Ok(())
+    }
+
+    fn execute(&mut self, stmt: &Stmt) -> Result<ControlFlow<Value>> {
+        self.visit_stmt(stmt)
+    }
+
+    pub fn execute_block(
+        &mut self,
+        statements: &[Stmt],
+        environment: Rc<RefCell<Environment>>,
+    ) -> Result<ControlFlow<Value>> {
+        let previous = std::mem::replace(&mut self.environment, environment);
+        let mut result = Ok(ControlFlow::Continue(()));
+
+        for statement in statements {
+            result = self.execute(statement);
+
+            if !matches!(result, Ok(ControlFlow::Continue(()))) {
+                break;
+            }
+        }
+
+        self.environment = previous;
+
+        result
+    }
+
+    fn evaluate(&mut self, expr: &Expr) -> Result<Value> {
+        self.visit_expr(expr)
+    }
+
+    fn lookup_variable(&self, id: ExprId, name: &Token) -> Result<Value> {
+        match self.locals.get(&id) {
+            Some(depth) => self.environment.borrow().get_at(*depth, name),
+            None => self.globals.borrow().get(name),
+        }
+    }
+
+    fn evaluate_binary(operator: &Token, left: Value, right: Value) -> Result<Value> {
+        let value = match (&operator.token_type, left, right) {
+            (TokenType::EqualEqual, left, right) => Value::Bool(left == right),
+            (TokenType::BangEqual, left, right) => Value::Bool(left != right),
+            (TokenType::Plus, Value::Number(a), Value::Number(b)) => Value::Number(a + b),
+            (TokenType::Plus, Value::Stringy(a), Value::Stringy(b)) => {
+                Value::Stringy(Symbol::intern(&format!("{}{}", a, b)))
+            }
+            (TokenType::Plus, _, _) => {
+                return Err(EvalErr {
+                    line: operator.line,
+                    span: operator.span,
+                    code: "E0405",
+                    message: "Operands must be two numbers or two strings".to_string(),
+                    label: "can't add these".to_string(),
+                })
+            }
+            (TokenType::Minus, Value::Number(a), Value::Number(b)) => Value::Number(a - b),
+            (TokenType::Star, Value::Number(a), Value::Number(b)) => Value::Number(a * b),
+            (TokenType::Slash, Value::Number(a), Value::Number(b)) => Value::Number(a / b),
+            (TokenType::Greater, Value::Number(a), Value::Number(b)) => Value::Bool(a > b),
+            (TokenType::GreaterEqual, Value::Number(a), Value::Number(b)) => Value::Bool(a >= b),
+            (TokenType::Less, Value::Number(a), Value::Number(b)) => Value::Bool(a < b),
+            (TokenType::LessEqual, Value::Number(a), Value::Number(b)) => Value::Bool(a <= b),
+            (
+                TokenType::Minus
+                | TokenType::Star
+                | TokenType::Slash
+                | TokenType::Greater
+                | TokenType::GreaterEqual
+                | TokenType::Less
+                | TokenType::LessEqual,
+                _,
+                _,
+            ) => {
+                return Err(EvalErr {
+                    line: operator.line,
+                    span: operator.span,
+                    code: "E0404",
+                    message: "Operands must be numbers".to_string(),
+                    label: "expected two numbers".to_string(),
+                })
+            }
+            (token_type, _, _) => {
+                return Err(EvalErr {
+                    line: operator.line,
+                    span: operator.span,
+                    code: "E0412",
+                    message: format!("Invalid binary operator: {}", token_type),
+                    label: "no such operator".to_string(),
+                })
+            }
+        };
+
+        Ok(value)
+    }
+}
+
+// `Break` carries a `return` value up to the enclosing function call
+impl StmtVisitor<Result<ControlFlow<Value>>> for Interpreter {
+    // Only statements can return, so evaluating an expression never breaks
+    fn visit_child_expr(&mut self, expr: &Expr) -> Result<ControlFlow<Value>> {
+        self.evaluate(expr)?;
+
+        Ok(ControlFlow::Continue(()))
+    }
+
+    fn visit_block(&mut self, statements: &[Stmt]) -> Result<ControlFlow<Value>> {
+        let environment = Environment::with_enclosing(self.environment.clone());
+
+        self.execute_block(statements, Rc::new(RefCell::new(environment)))
+    }
+
+    fn visit_class(
+        &mut self,
+        name: &Token,
+        superclass: Option<&Expr>,
+        methods: &[Rc<FunctionDecl>],
+    ) -> Result<ControlFlow<Value>> {
+        let superclass = match superclass {
+            Some(expr) => match self.evaluate(expr)? {
+                Value::Class(class) => Some(class),
+                _ => {
+                    // Point at the superclass named after `<`, not the class
+                    let operand = match expr {
+                        Expr::Variable {
+                            name: superclass_name,
+                            ..
+                        } => superclass_name,
+                        _ => name,
+                    };
+
+                    return Err(EvalErr {
+                        line: operand.line,
+                        span: operand.span,
+                        code: "E0408",
+                        message: "Superclass must be a class".to_string(),
+                        label: "not a class".to_string(),
+                    });
+                }
+            },
+            None => None,
+        };
+
+        self.environment
+            .borrow_mut()
+            .define(name.symbol(), Value::Nil);
+
+        // Methods of a subclass close over a scope that binds `super`
+        let enclosing = superclass.as_ref().map(|superclass| {
+            let mut environment = Environment::with_enclosing(self.environment.clone());
+            environment.define(Symbol::SUPER, Value::Class(superclass.clone()));
+
+            std::mem::replace(&mut self.environment, Rc::new(RefCell::new(environment)))
+        });
+
+        let methods = methods
+            .iter()
+            .map(|method| {
+                let function = Function::new(
+                    method.clone(),
+                    self.environment.clone(),
+                    method.name.symbol() == Symbol::INIT,
+                );
+
+                (method.name.symbol(), Rc::new(function))
+            })
+            .collect();
+
+        if let Some(enclosing) = enclosing {
+            self.environment = enclosing;
+        }
+
+        let class = Class::new(name.name(), superclass, methods);
+        self.environment
+            .borrow_mut()
+            .assign(name, Value::Class(Rc::new(class)))?;
+
+        Ok(ControlFlow::Continue(()))
+    }
+
+    fn visit_function(&mut self, declaration: &Rc<FunctionDecl>) -> Result<ControlFlow<Value>> {
+        let function = Function::new(declaration.clone(), self.environment.clone(), false);
+
+        self.environment.borrow_mut().define(
+            declaration.name.symbol(),
+            Value::Function(Rc::new(function)),
+        );
+
+        Ok(ControlFlow::Continue(()))
+    }
+
+    fn visit_if(
+        &mut self,
+        condition: &Expr,
+        then_branch: &Stmt,
+        else_branch: Option<&Stmt>,
+    ) -> Result<ControlFlow<Value>> {
+        if self.evaluate(condition)?.is_truthy() {
+            self.execute(then_branch)
+        } else if let Some(else_branch) = else_branch {
+            self.execute(else_branch)
+        } else {
+            Ok(ControlFlow::Continue(()))
+        }
+    }
+
+    fn visit_print(&mut self, expression: &Expr) -> Result<ControlFlow<Value>> {
+        let value = self.evaluate(expression)?;
+        writeln!(self.output, "{}", value).map_err(|err| GeneralErr {
+            code: "E0502",
+            message: format!("Failed to write output: {}", err),
+        })?;
+
+        Ok(ControlFlow::Continue(()))
+    }
+
+    fn visit_return(&mut self, _: &Token, value: Option<&Expr>) -> Result<ControlFlow<Value>> {
+        let value = match value {
+            Some(value) => self.evaluate(value)?,
+            None => Value::Nil,
+        };
+
+        Ok(ControlFlow::Break(value))
+    }
+
+    fn visit_var(
+        &mut self,
+        name: &Token,
+        initializer: Option<&Expr>,
+    ) -> Result<ControlFlow<Value>> {
+        let value = match initializer {
+            Some(initializer) => self.evaluate(initializer)?,
+            None => Value::Nil,
+        };
+
+        self.environment.borrow_mut().define(name.symbol(), value);
+
+        Ok(ControlFlow::Continue(()))
+    }
+
+    fn visit_while(&mut self, condition: &Expr, body: &Stmt) -> Result<ControlFlow<Value>> {
+        while self.evaluate(condition)?.is_truthy() {
+            if let ControlFlow::Break(value) = self.execute(body)? {
+                return Ok(ControlFlow::Break(value));
+            }
+        }
+
+        Ok(ControlFlow::Continue(()))
+    }
+}
+
+impl ExprVisitor<Result<Value>> for Interpreter {
+    fn visit_assign(&mut self, id: ExprId, name: &Token, value: &Expr) -> Result<Value> {
+        let value = self.evaluate(value)?;
+
+        match self.locals.get(&id) {
+            Some(depth) => {
+                self.environment
+                    .borrow_mut()
+                    .assign_at(*depth, name, value.clone())?;
+            }
+            None => self.globals.borrow_mut().assign(name, value.clone())?,
+        }
+
+        Ok(value)
+    }
+
+    fn visit_binary(&mut self, left: &Expr, operator: &Token, right: &Expr) -> Result<Value> {
+        let left = self.evaluate(left)?;
+        let right = self.evaluate(right)?;
+
+        Self::evaluate_binary(operator, left, right)
+    }
+
+    fn visit_call(&mut self, callee: &Expr, paren: &Token, arguments: &[Expr]) -> Result<Value> {
+        let callee = self.evaluate(callee)?;
+        let arguments = arguments
+            .iter()
+            .map(|argument| self.evaluate(argument))
+            .collect::<Result<Vec<_>>>()?;
+
+        let callable: &dyn Callable = match &callee {
+            Value::Function(function) => function.as_ref(),
+            Value::NativeFunction(function) => function.as_ref(),
+            Value::Class(class) => class,
+            _ => {
+                return Err(EvalErr {
+                    line: paren.line,
+                    span: paren.span,
+                    code: "E0409",
+                    message: "Can only call functions and classes".to_string(),
+                    label: "not callable".to_string(),
+                })
+            }
+        };
+
+        if arguments.len() != callable.arity() {
+            return Err(EvalErr {
+                line: paren.line,
+                span: paren.span,
+                code: "E0410",
+                message: format!(
+                    "Expected {} arguments but got {}",
+                    callable.arity(),
+                    arguments.len()
+                ),
+                label: "wrong number of arguments".to_string(),
+            });
+        }
+
+        if self.call_depth == MAX_CALL_DEPTH {
+            return Err(EvalErr {
+                line: paren.line,
+                span: paren.span,
+                code: "E0411",
+                message: "Stack overflow".to_string(),
+                label: "too many nested calls".to_string(),
+            });
+        }
+
+        self.call_depth += 1;
+        let result = callable.call(self, arguments);
+        self.call_depth -= 1;
+
+        result
+    }
+
+    fn visit_get(&mut self, object: &Expr, name: &Token) -> Result<Value> {
+        match self.evaluate(object)? {
+            Value::Instance(instance) => Instance::get(&instance, name),
+            _ => Err(EvalErr {
+                line: name.line,
+                span: name.span,
+                code: "E0406",
+                message: "Only instances have properties".to_string(),
+                label: "not an instance".to_string(),
+            }),
+        }
+    }
+
+    fn visit_grouping(&mut self, expression: &Expr) -> Result<Value> {
+        self.evaluate(expression)
+    }
+
+    fn visit_literal(&mut self, value: &Literal) -> Result<Value> {
+        Ok(Value::from(value))
+    }
+
+    fn visit_logical(&mut self, left: &Expr, operator: &Token, right: &Expr) -> Result<Value> {
+        let left = self.evaluate(left)?;
+
+        // Short-circuit and hand back whichever operand decided the result
+        match operator.token_type {
+            TokenType::Or if left.is_truthy() => Ok(left),
+            TokenType::And if !left.is_truthy() => Ok(left),
+            _ => self.evaluate(right),
+        }
+    }
+
+    fn visit_set(&mut self, object: &Expr, name: &Token, value: &Expr) -> Result<Value> {
+        let Value::Instance(instance) = self.evaluate(object)? else {
+            return Err(EvalErr {
+                line: name.line,
+                span: name.span,
+                code: "E0407",
+                message: "Only instances have fields".to_string(),
+                label: "not an instance".to_string(),
+            });
+        };
+
+        let value = self.evaluate(value)?;
+        instance.borrow_mut().set(name, value.clone());
+
+        Ok(value)
+    }
+
+    fn visit_super(&mut self, id: ExprId, keyword: &Token, method: &Token) -> Result<Value> {
+        let depth = *self
+            .locals
+            .get(&id)
+            .expect("The resolver binds every 'super' expression");
+
+        let Value::Class(superclass) = self.environment.borrow().get_at(depth, keyword)? else {
+            unreachable!("'super' is always bound to a class");
+        };
+
+        // `this` lives in the scope just inside the one binding `super`
+        let this = Token::synthetic(TokenType::This, keyword.line);
+        let Value::Instance(instance) = self.environment.borrow().get_at(depth - 1, &this)? else {
+            unreachable!("'this' is always bound to an instance");
+        };
+
+        match superclass.find_method(method.symbol()) {
+            Some(method) => Ok(Value::Function(Rc::new(method.bind(instance)))),
+            None => Err(EvalErr {
+                line: method.line,
+                span: method.span,
+                code: "E0402",
+                message: format!("Undefined property '{}'", method.name()),
+                label: "no such property".to_string(),
+            }),
+        }
+    }
+
+    fn visit_this(&mut self, id: ExprId, keyword: &Token) -> Result<Value> {
+        self.lookup_variable(id, keyword)
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: &Expr) -> Result<Value> {
+        let right = self.evaluate(right)?;
+
+        match (&operator.token_type, right) {
+            (TokenType::Minus, Value::Number(num)) => Ok(Value::Number(-num)),
+            (TokenType::Minus, _) => Err(EvalErr {
+                line: operator.line,
+                span: operator.span,
+                code: "E0403",
+                message: "Operand must be a number".to_string(),
+                label: "expected a number".to_string(),
+            }),
+            (TokenType::Bang, value) => Ok(Value::Bool(!value.is_truthy())),
+            (token_type, _) => Err(EvalErr {
+                line: operator.line,
+                span: operator.span,
+                code: "E0412",
+                message: format!("Invalid unary operator: {}", token_type),
+                label: "no such operator".to_string(),
+            }),
+        }
+    }
+
+    fn visit_variable(&mut self, id: ExprId, name: &Token) -> Result<Value> {
+        self.lookup_variable(id, name)
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parser::Parser, resolver::Resolver, scanner::Scanner, test_util::SharedBuf};
+
+    use super::*;
+
+    fn run(src: &str) -> (String, Result<()>) {
+        let buf = SharedBuf::default();
+        let mut interpreter = Interpreter::with_output(Box::new(buf.clone()));
+
+        let tokens = Scanner::new(src.to_string()).scan_tokens().unwrap();
+        let statements = Parser::new(&tokens).parse().unwrap();
+        interpreter.resolve(Resolver::new().resolve(&statements).unwrap());
+        let result = interpreter.interpret(&statements);
+
+        (buf.contents(), result)
+    }
+
+    #[test]
+    fn check_expressions() {
+        let (output, result) = run("print (1 + 2) * 3 - 4 / 2;
+            print \"foo\" + \"bar\";
+            print !nil == !false;
+            print 1 == \"1\";");
+
+        assert!(result.is_ok());
+        assert_eq!(output, "7\nfoobar\ntrue\nfalse\n");
+    }
+
+    #[test]
+    fn check_type_errors() {
+        let (_, result) = run("print -(2 >= 3);");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "[line 1] Runtime error: Operand must be a number"
+        );
+
+        let (_, result) = run("\n1 + \"a\";");
+        assert!(matches!(result, Err(EvalErr { line: 2, .. })));
+    }
+
+    #[test]
+    fn check_variables() {
+        let (output, result) = run("var a = 1; var b; print a; print b; var a = a + 1; print a;");
+
+        assert!(result.is_ok());
+        assert_eq!(output, "1\nnil\n2\n");
+
+        let (_, result) = run("print c;");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "[line 1] Runtime error: Undefined variable 'c'"
+        );
+    }
+
+    #[test]
+    fn check_scopes() {
+        let (output, result) = run("var a = \"global a\";
+            var b = \"global b\";
+            {
+                var a = \"outer a\";
+                {
+                    var a = \"inner a\";
+                    print a;
+                    b = \"assigned b\";
+                }
+                print a;
+            }
+            print a;
+            print b;");
+
+        assert!(result.is_ok());
+        assert_eq!(output, "inner a\nouter a\nglobal a\nassigned b\n");
+    }
+
+    #[test]
+    fn check_assignment() {
+        let (output, result) = run("var a; var b; a = b = 3; print a + b;");
+
+        assert!(result.is_ok());
+        assert_eq!(output, "6\n");
+
+        let (_, result) = run("{ var x = 1; }\nx = 2;");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "[line 2] Runtime error: Undefined variable 'x'"
+        );
+    }
+
+    #[test]
+    fn check_control_flow() {
+        let (output, result) = run("if (1 > 2) print \"then\"; else print \"else\";
+            var i = 0;
+            while (i < 3) i = i + 1;
+            print i;
+            var a = 0;
+            var b = 1;
+            for (var n = 0; n < 5; n = n + 1) {
+                var temp = a;
+                a = b;
+                b = temp + b;
+            }
+            print a;");
+
+        assert!(result.is_ok());
+        assert_eq!(output, "else\n3\n5\n");
+    }
+
+    #[test]
+    fn check_logical() {
+        let (output, result) = run("print nil or \"yes\";
+            print 0 and \"zero is truthy\";
+            print false and undefined;
+            print true or undefined;");
+
+        assert!(result.is_ok());
+        assert_eq!(output, "yes\nzero is truthy\nfalse\ntrue\n");
+    }
+
+    #[test]
+    fn check_functions() {
+        let (output, result) = run("fun fib(n) {
+                if (n < 2) return n;
+                return fib(n - 1) + fib(n - 2);
+            }
+            fun noop() {}
+            print fib(10);
+            print noop();
+            print fib;
+            print clock;");
+
+        assert!(result.is_ok());
+        assert_eq!(output, "55\nnil\n<fn fib>\n<native fn>\n");
+    }
+
+    #[test]
+    fn check_closures() {
+        let (output, result) = run("fun makeCounter() {
+                var i = 0;
+                fun count() {
+                    i = i + 1;
+                    return i;
+                }
+                return count;
+            }
+            var counter = makeCounter();
+            counter();
+            print counter();");
+
+        assert!(result.is_ok());
+        assert_eq!(output, "2\n");
+    }
+
+    #[test]
+    fn check_call_errors() {
+        let (_, result) = run("fun f(a, b) {}\nf(1);");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "[line 2] Runtime error: Expected 2 arguments but got 1"
+        );
+
+        let (_, result) = run("\"not a function\"();");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "[line 1] Runtime error: Can only call functions and classes"
+        );
+    }
+
+    #[test]
+    fn check_static_binding() {
+        let (output, result) = run("var a = \"global\";
+            {
+                fun showA() {
+                    print a;
+                }
+                showA();
+                var a = \"block\";
+                showA();
+            }");
+
+        assert!(result.is_ok());
+        assert_eq!(output, "global\nglobal\n");
+    }
+
+    #[test]
+    fn check_classes() {
+        let (output, result) = run("class Point {
+                init(x, y) {
+                    this.x = x;
+                    this.y = y;
+                }
+
+                sum() {
+                    return this.x + this.y;
+                }
+            }
+            var p = Point(1, 2);
+            print Point;
+            print p;
+            print p.sum();
+            p.x = 10;
+            var sum = p.sum;
+            print sum();
+            print p.init(3, 4) == p;
+            print p.x;");
+
+        assert!(result.is_ok());
+        assert_eq!(output, "Point\nPoint instance\n3\n12\ntrue\n3\n");
+    }
+
+    #[test]
+    fn check_property_errors() {
+        let (_, result) = run("class A {}\nprint A().missing;");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "[line 2] Runtime error: Undefined property 'missing'"
+        );
+
+        let (_, result) = run("var n = 1;\nn.field = 2;");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "[line 2] Runtime error: Only instances have fields"
+        );
+    }
+
+    #[test]
+    fn check_inheritance() {
+        let (output, result) = run("class A {
+                init(name) {
+                    this.name = name;
+                }
+
+                greet() {
+                    return \"A says hi to \" + this.name;
+                }
+
+                kind() {
+                    return \"A\";
+                }
+            }
+            class B < A {
+                greet() {
+                    return super.greet() + \" via \" + this.kind();
+                }
+            }
+            class C < B {
+                kind() {
+                    return \"C\";
+                }
+            }
+            print C(\"bob\").greet();");
+
+        assert!(result.is_ok());
+        assert_eq!(output, "A says hi to bob via C\n");
+
+        let (_, result) = run("var NotAClass = 1;\nclass A < NotAClass {}");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "[line 2] Runtime error: Superclass must be a class"
+        );
+    }
+}
@@ -1,67 +1,475 @@
 mod ast;
+mod class;
+mod cst;
+mod diagnostic;
+mod environment;
 mod error;
+mod formatter;
+mod function;
+mod interpreter;
 mod parser;
+mod resolver;
 mod scanner;
+mod symbol;
+#[cfg(test)]
+mod test_util;
 mod token;
+mod value;
+mod visitor;
+mod vm;
 
 use std::{
+    collections::HashMap,
     env::args,
-    fs::read_to_string,
-    io::{stdin, stdout, Write},
+    fs::{read_to_string, write},
+    io::{self, stdin, stdout, BufRead, ErrorKind, StdoutLock, Write},
     process::exit,
+    thread,
 };
 
+use ast::{ExprId, Stmt};
+use diagnostic::Diagnostic;
+use error::Error;
+use formatter::Formatter;
+use interpreter::Interpreter;
 use parser::Parser;
+use resolver::Resolver;
 use scanner::Scanner;
+use token::Token;
+use vm::Vm;
 
-fn run_file(path: String) {
-    let contents = read_to_string(path).expect("Error while reading input file...");
-    run(contents);
+// Exit codes from sysexits.h
+const EX_USAGE: i32 = 64;
+const EX_DATAERR: i32 = 65;
+const EX_SOFTWARE: i32 = 70;
+const EX_IOERR: i32 = 74;
+
+// `fmt --check` found a file that isn't formatted, like `rustfmt --check`
+const EX_UNFORMATTED: i32 = 1;
+
+// The tree-walker recurses on the native stack for every Lox call, so it needs
+// far more than the default main thread stack to reach its call depth limit
+const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+const USAGE: &str =
+    "Usage: rlox [run] [--backend tree|vm | --disassemble] [path/to/script [arguments...]]?
+       rlox tokens|ast [--format text|json] path/to/script
+       rlox ast --dot path/to/script
+       rlox check path/to/script
+       rlox fmt [--check] path/to/script";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Text,
+    Json,
+    Dot,
+}
+
+enum Backend {
+    TreeWalk(Interpreter),
+    Vm(Box<Vm>),
+}
+
+impl Backend {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "tree" => Some(Backend::TreeWalk(Interpreter::new())),
+            "vm" => Some(Backend::Vm(Box::default())),
+            _ => None,
+        }
+    }
+
+    fn set_args(&mut self, args: Vec<String>) {
+        match self {
+            Backend::TreeWalk(interpreter) => interpreter.set_args(args),
+            Backend::Vm(vm) => vm.set_args(args),
+        }
+    }
+}
+
+fn read_file(path: &str) -> Result<String, i32> {
+    read_to_string(path).map_err(|err| {
+        eprintln!("Could not read '{}': {}", path, err);
+        EX_IOERR
+    })
 }
 
-fn run_prompt() {
+// Front-end stages shared by every command. Each reports its own errors, so
+// callers only need to pass the exit code along.
+fn scan(name: &str, src: &str) -> Result<Vec<Token>, i32> {
+    scan_from(name, src, 0, 1)
+}
+
+// Scans only `src[start..]`, which begins on line `line`
+fn scan_from(name: &str, src: &str, start: usize, line: usize) -> Result<Vec<Token>, i32> {
+    Scanner::with_start(src.to_string(), start, line)
+        .scan_tokens()
+        .map_err(|diagnostics| {
+            report(name, src, &diagnostics);
+            EX_DATAERR
+        })
+}
+
+fn parse(name: &str, src: &str, tokens: &[Token]) -> Result<Vec<Stmt>, i32> {
+    Parser::new(tokens)
+        .parse()
+        .map_err(|errors| report_errors(name, src, &errors))
+}
+
+fn resolve(name: &str, src: &str, statements: &[Stmt]) -> Result<HashMap<ExprId, usize>, i32> {
+    Resolver::new()
+        .resolve(statements)
+        .map_err(|errors| report_errors(name, src, &errors))
+}
+
+fn tokens_file(path: &str, format: Format) -> Result<(), i32> {
+    let contents = read_file(path)?;
+    let tokens = scan(path, &contents)?;
+
+    if format == Format::Json {
+        return print_json(&tokens);
+    }
+
+    write_output(|out| {
+        tokens.iter().try_for_each(|token| {
+            writeln!(
+                out,
+                "{}:{} {}..{} {}",
+                token.line, token.column, token.span.start, token.span.end, token.token_type
+            )
+        })
+    })
+}
+
+fn ast_file(path: &str, format: Format) -> Result<(), i32> {
+    let contents = read_file(path)?;
+    let tokens = scan(path, &contents)?;
+    let statements = parse(path, &contents, &tokens)?;
+
+    match format {
+        Format::Json => print_json(&statements),
+        Format::Dot => write_output(|out| write!(out, "{}", Stmt::dot(&statements))),
+        Format::Text => write_output(|out| {
+            statements
+                .iter()
+                .try_for_each(|statement| writeln!(out, "{}", statement.print()))
+        }),
+    }
+}
+
+// Listings are often piped into something like `head`, so a closed pipe
+// just ends the output early
+fn write_output(write: impl FnOnce(&mut StdoutLock) -> io::Result<()>) -> Result<(), i32> {
+    let mut out = stdout().lock();
+
+    match write(&mut out).and_then(|()| out.flush()) {
+        Err(err) if err.kind() != ErrorKind::BrokenPipe => {
+            eprintln!("Could not write output: {}", err);
+            Err(EX_IOERR)
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(feature = "serde")]
+fn print_json(value: &impl serde::Serialize) -> Result<(), i32> {
+    let json = serde_json::to_string_pretty(value).map_err(|err| {
+        eprintln!("Could not serialize output: {}", err);
+        EX_SOFTWARE
+    })?;
+
+    write_output(|out| writeln!(out, "{}", json))
+}
+
+// `--format json` is turned away up front when serde isn't compiled in
+#[cfg(not(feature = "serde"))]
+fn print_json<T>(_: &T) -> Result<(), i32> {
+    unreachable!("JSON output requires the serde feature")
+}
+
+fn check_file(path: &str) -> Result<(), i32> {
+    let contents = read_file(path)?;
+    let tokens = scan(path, &contents)?;
+    let statements = parse(path, &contents, &tokens)?;
+    resolve(path, &contents, &statements)?;
+
+    Ok(())
+}
+
+// Rewrites the file in place, or with `check` only reports whether it would.
+// Programs that don't parse are left alone.
+fn fmt_file(path: &str, check: bool) -> Result<(), i32> {
+    let contents = read_file(path)?;
+    let tokens = scan(path, &contents)?;
+    let program = Parser::with_cst(&contents, &tokens)
+        .parse_cst()
+        .map_err(|errors| report_errors(path, &contents, &errors))?;
+
+    let formatted = Formatter::new(&program).format();
+
+    if formatted == contents {
+        return Ok(());
+    }
+
+    if check {
+        eprintln!("'{}' is not formatted", path);
+        return Err(EX_UNFORMATTED);
+    }
+
+    write(path, formatted).map_err(|err| {
+        eprintln!("Could not write '{}': {}", path, err);
+        EX_IOERR
+    })
+}
+
+fn run_file(backend: &mut Backend, path: &str) -> Result<(), i32> {
+    let contents = read_file(path)?;
+
+    run(backend, path, &contents, 0, 1)
+}
+
+fn disassemble_file(path: &str) -> Result<(), i32> {
+    let contents = read_file(path)?;
+    let tokens = scan(path, &contents)?;
+    let statements = parse(path, &contents, &tokens)?;
+    resolve(path, &contents, &statements)?;
+
+    let listing = Vm::new()
+        .disassemble(&statements)
+        .map_err(|err| report_errors(path, &contents, &[err]))?;
+
+    print!("{}", listing);
+
+    Ok(())
+}
+
+// Errors are reported and the session carries on; only failing to read
+// the input ends it early. Every line is kept, since functions defined on
+// earlier lines can fail later and their diagnostics need the source.
+fn run_prompt(
+    backend: &mut Backend,
+    mut input: impl BufRead,
+    mut output: impl Write,
+) -> Result<(), i32> {
+    let mut session = String::new();
+    let mut line = 1;
     let mut buf = String::new();
 
     loop {
         buf.clear();
-        print!("> ");
-        stdout().flush().expect("Failed to flush stdout...");
-        stdin().read_line(&mut buf).expect("Failed to read line...");
+        let _ = write!(output, "> ");
+        let _ = output.flush();
+
+        match input.read_line(&mut buf) {
+            Ok(0) => {
+                let _ = writeln!(output);
+                return Ok(());
+            }
+            Ok(_) => {}
+            Err(err) => {
+                eprintln!("Could not read input: {}", err);
+                return Err(EX_IOERR);
+            }
+        }
 
         if matches!(buf.as_str().trim(), "exit" | "quit") {
-            println!("Goodbye!");
-            exit(0);
-        } else {
-            run(buf.to_string());
+            let _ = writeln!(output, "Goodbye!");
+            return Ok(());
+        }
+
+        let start = session.len();
+        session.push_str(buf.trim_end_matches('\n'));
+        session.push('\n');
+
+        let _ = run(backend, "<repl>", &session, start, line);
+        line += 1;
+    }
+}
+
+// `name` identifies the source in diagnostics, e.g. the script's path. Only
+// `src[start..]`, beginning on line `line`, is run; the rest is there for
+// diagnostics about code from before.
+fn run(backend: &mut Backend, name: &str, src: &str, start: usize, line: usize) -> Result<(), i32> {
+    let tokens = scan_from(name, src, start, line)?;
+    let statements = parse(name, src, &tokens)?;
+    let locals = resolve(name, src, &statements)?;
+
+    let result = match backend {
+        Backend::TreeWalk(interpreter) => {
+            interpreter.resolve(locals);
+            interpreter.interpret(&statements)
+        }
+        Backend::Vm(vm) => vm.interpret(&statements),
+    };
+
+    result.map_err(|err| report_errors(name, src, &[err]))
+}
+
+fn report(name: &str, src: &str, diagnostics: &[Diagnostic]) {
+    diagnostics
+        .iter()
+        .for_each(|diagnostic| eprint!("{}", diagnostic.render(name, src)));
+}
+
+// Reports `errors` and returns the exit code for the most severe one
+fn report_errors(name: &str, src: &str, errors: &[Error]) -> i32 {
+    let diagnostics: Vec<Diagnostic> = errors.iter().map(Error::diagnostic).collect();
+    report(name, src, &diagnostics);
+
+    errors
+        .iter()
+        .map(|err| match err {
+            Error::ParseErr { .. } | Error::ResolveErr { .. } | Error::CompileErr { .. } => {
+                EX_DATAERR
+            }
+            Error::EvalErr { .. } | Error::GeneralErr { .. } => EX_SOFTWARE,
+        })
+        .max()
+        .unwrap_or(EX_SOFTWARE)
+}
+
+fn usage_error() -> i32 {
+    eprintln!("{}", USAGE);
+    EX_USAGE
+}
+
+fn cli(args: Vec<String>) -> Result<(), i32> {
+    let mut args = args.into_iter().peekable();
+
+    // Without a subcommand the arguments are treated as for `run`
+    let command = args
+        .next_if(|arg| matches!(arg.as_str(), "tokens" | "ast" | "check" | "fmt" | "run"))
+        .unwrap_or_else(|| "run".to_string());
+
+    if command == "run" {
+        return run_command(args.collect());
+    }
+
+    let mut format = Format::Text;
+    let mut check = false;
+    let mut path = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" if matches!(command.as_str(), "tokens" | "ast") => {
+                format = parse_format(args.next())?
+            }
+            "--dot" if command == "ast" => format = Format::Dot,
+            "--check" if command == "fmt" => check = true,
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(usage_error()),
+        }
+    }
+
+    let path = path.ok_or_else(usage_error)?;
+
+    match command.as_str() {
+        "tokens" => tokens_file(&path, format),
+        "ast" => ast_file(&path, format),
+        "fmt" => fmt_file(&path, check),
+        _ => check_file(&path),
+    }
+}
+
+fn parse_format(format: Option<String>) -> Result<Format, i32> {
+    match format.as_deref() {
+        Some("text") => Ok(Format::Text),
+        Some("json") if cfg!(feature = "serde") => Ok(Format::Json),
+        Some("json") => {
+            eprintln!("JSON output needs rlox to be built with `--features serde`");
+            Err(EX_USAGE)
         }
+        _ => Err(usage_error()),
     }
 }
 
-fn run(src: String) {
-    let mut scanner = Scanner::new(src);
-    scanner.scan_tokens();
+// Flags are only read before the script path; everything after it belongs to
+// the script, even if it looks like one of ours
+fn run_command(args: Vec<String>) -> Result<(), i32> {
+    let mut args = args.into_iter();
+    let mut backend_name = "tree".to_string();
+    let mut disassemble = false;
+    let mut positional = vec![];
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--disassemble" => disassemble = true,
+            "--backend" => backend_name = args.next().ok_or_else(usage_error)?,
+            _ => {
+                positional.push(arg);
+                positional.extend(args.by_ref());
+            }
+        }
+    }
+
+    if disassemble {
+        let [path] = positional.as_slice() else {
+            return Err(usage_error());
+        };
+
+        return disassemble_file(path);
+    }
 
-    let mut parser = Parser::new(&scanner.tokens);
-    let expr = parser.parse().unwrap();
+    let Some(mut backend) = Backend::from_name(&backend_name) else {
+        eprintln!("Unknown backend: {}\n{}", backend_name, USAGE);
+        return Err(EX_USAGE);
+    };
 
-    println!("{:#?}", expr);
+    match positional.split_first() {
+        None => run_prompt(&mut backend, stdin().lock(), stdout()),
+        Some((path, script_args)) => {
+            backend.set_args(script_args.to_vec());
+            run_file(&mut backend, path)
+        }
+    }
 }
 
 fn main() {
     let args = args().skip(1).collect::<Vec<String>>();
 
-    match args.len() {
-        2.. => {
-            eprintln!("Usage: rlox [path/to/script]?");
-            exit(64);
+    let cli = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(|| cli(args));
+
+    let code = match cli.map(|handle| handle.join()) {
+        Ok(Ok(Ok(()))) => 0,
+        Ok(Ok(Err(code))) => code,
+        // A panic has already printed its message
+        Ok(Err(_)) => EX_SOFTWARE,
+        Err(err) => {
+            eprintln!("Could not start the interpreter: {}", err);
+            EX_SOFTWARE
         }
-        1 => run_file(
-            args.last()
-                .expect("Error while reading args...")
-                .to_string(),
-        ),
-        0 => run_prompt(),
-    }
+    };
+
+    exit(code);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::sink;
 
-    println!("{:#?}", args);
+    use crate::test_util::SharedBuf;
+
+    use super::*;
+
+    #[test]
+    fn check_repl_errors() {
+        // The error is in code from the first line but raised on the second,
+        // so its diagnostic has to be rendered against both
+        let input = "fun f() { return 1 + \"a\"; }\nf();\nprint \"still alive\";\n";
+
+        let backends: [fn(SharedBuf) -> Backend; 2] = [
+            |buf| Backend::TreeWalk(Interpreter::with_output(Box::new(buf))),
+            |buf| Backend::Vm(Box::new(Vm::with_output(Box::new(buf)))),
+        ];
+
+        for backend in backends {
+            let buf = SharedBuf::default();
+            let mut backend = backend(buf.clone());
+
+            assert_eq!(run_prompt(&mut backend, input.as_bytes(), sink()), Ok(()));
+            assert_eq!(buf.contents(), "still alive\n");
+        }
+    }
 }
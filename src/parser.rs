@@ -1,24 +1,94 @@
+use std::rc::Rc;
+
 use crate::{
-    ast::{Expr, Literal},
-    error::{Error::ParseErr, Result},
+    ast::{Expr, ExprId, FunctionDecl, Literal, Stmt},
+    cst::{CstBuilder, Node, NodeKind},
+    error::{
+        Error::{self, ParseErr},
+        Result,
+    },
     token::{Token, TokenType},
 };
 
-pub struct Parser<'a> {
-    tokens: &'a Vec<Token>,
+const MAX_ARGS: usize = 255;
+
+pub struct Parser {
+    tokens: Vec<Token>,
     current: usize,
+    errors: Vec<Error>,
+    // Only set up by `with_cst`, and dropped at the first syntax error
+    cst: Option<CstBuilder>,
 }
 
-impl<'a> Parser<'a> {
-    pub fn new(tokens: &'a Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+impl Parser {
+    // Comments carry no meaning, so they're dropped before parsing
+    pub fn new(tokens: &[Token]) -> Self {
+        Self {
+            tokens: tokens
+                .iter()
+                .filter(|token| token.token_type != TokenType::Comment)
+                .copied()
+                .collect(),
+            current: 0,
+            errors: vec![],
+            cst: None,
+        }
+    }
+
+    // Also records a lossless CST of `src` as it parses, for `parse_cst`
+    pub fn with_cst(src: &str, tokens: &[Token]) -> Self {
+        Self {
+            cst: Some(CstBuilder::new(src, tokens)),
+            ..Self::new(tokens)
+        }
+    }
+
+    // Parses the whole program, collecting every syntax error. After an error
+    // the parser skips ahead to the next statement and carries on.
+    pub fn parse(mut self) -> std::result::Result<Vec<Stmt>, Vec<Error>> {
+        let statements = self.program();
+
+        if self.errors.is_empty() {
+            Ok(statements)
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    // Parses the whole program like `parse`, but hands back the CST instead.
+    // The tree is only built for programs without syntax errors.
+    pub fn parse_cst(mut self) -> std::result::Result<Node, Vec<Error>> {
+        self.program();
+
+        if self.errors.is_empty() {
+            Ok(self
+                .cst
+                .expect("Only parsers made by `with_cst` build a CST")
+                .build())
+        } else {
+            Err(self.errors)
+        }
     }
 
-    pub fn parse(&mut self) -> Result<Expr> {
-        self.expression()
+    fn program(&mut self) -> Vec<Stmt> {
+        let mut statements = vec![];
+
+        while !self.at_end() {
+            match self.declaration() {
+                Ok(statement) => statements.push(statement),
+                Err(err) => {
+                    self.errors.push(err);
+                    // Unwinding left nodes open, so the tree is beyond repair
+                    self.cst = None;
+                    self.synchronize();
+                }
+            }
+        }
+
+        statements
     }
 
-    #[allow(dead_code)]
+    // Discards tokens until the likely start of the next statement
     fn synchronize(&mut self) {
         self.advance();
 
@@ -43,19 +113,339 @@ impl<'a> Parser<'a> {
         }
     }
 
+    fn declaration(&mut self) -> Result<Stmt> {
+        if self.match_token(&[TokenType::Class]) {
+            self.class_declaration()
+        } else if self.match_token(&[TokenType::Fun]) {
+            self.start_with_previous(NodeKind::FunDecl);
+            let function = self.function("function")?;
+            self.finish();
+
+            Ok(Stmt::Function(function))
+        } else if self.match_token(&[TokenType::Var]) {
+            self.var_declaration()
+        } else {
+            self.statement()
+        }
+    }
+
+    fn class_declaration(&mut self) -> Result<Stmt> {
+        self.start_with_previous(NodeKind::ClassDecl);
+        let name = self.consume(TokenType::IDENTIFIER, "Expect class name")?;
+
+        let superclass = if self.match_token(&[TokenType::Less]) {
+            let name = self.consume(TokenType::IDENTIFIER, "Expect superclass name")?;
+            self.start_with_previous(NodeKind::Variable);
+            self.finish();
+
+            Some(Expr::Variable {
+                id: ExprId::new(),
+                name,
+            })
+        } else {
+            None
+        };
+
+        self.consume(TokenType::LeftBrace, "Expect '{' before class body")?;
+
+        let mut methods = vec![];
+
+        while !self.check_token(&TokenType::RightBrace) && !self.at_end() {
+            self.start(NodeKind::FunDecl);
+            methods.push(self.function("method")?);
+            self.finish();
+        }
+
+        self.consume(TokenType::RightBrace, "Expect '}' after class body")?;
+        self.finish();
+
+        Ok(Stmt::Class {
+            name,
+            superclass,
+            methods,
+        })
+    }
+
+    fn function(&mut self, kind: &str) -> Result<Rc<FunctionDecl>> {
+        let name = self.consume(TokenType::IDENTIFIER, &format!("Expect {} name", kind))?;
+        self.start(NodeKind::Params);
+        self.consume(
+            TokenType::LeftParen,
+            &format!("Expect '(' after {} name", kind),
+        )?;
+
+        let mut params = vec![];
+
+        if !self.check_token(&TokenType::RightParen) {
+            loop {
+                if params.len() >= MAX_ARGS {
+                    // Reported without unwinding since the parser isn't confused
+                    let err = self.error(
+                        self.peek(),
+                        "E0104",
+                        &format!("Can't have more than {} parameters", MAX_ARGS),
+                        "one parameter too many",
+                    );
+                    self.errors.push(err);
+                }
+
+                params.push(self.consume(TokenType::IDENTIFIER, "Expect parameter name")?);
+
+                if !self.match_token(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+
+        self.consume(TokenType::RightParen, "Expect ')' after parameters")?;
+        self.finish();
+        self.consume(
+            TokenType::LeftBrace,
+            &format!("Expect '{{' before {} body", kind),
+        )?;
+
+        let body = self.block()?;
+
+        Ok(Rc::new(FunctionDecl { name, params, body }))
+    }
+
+    fn var_declaration(&mut self) -> Result<Stmt> {
+        self.start_with_previous(NodeKind::VarDecl);
+        let name = self.consume(TokenType::IDENTIFIER, "Expect variable name")?;
+
+        let initializer = if self.match_token(&[TokenType::Equal]) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+
+        self.consume(
+            TokenType::Semicolon,
+            "Expect ';' after variable declaration",
+        )?;
+        self.finish();
+
+        Ok(Stmt::Var { name, initializer })
+    }
+
+    fn statement(&mut self) -> Result<Stmt> {
+        if self.match_token(&[TokenType::For]) {
+            self.for_statement()
+        } else if self.match_token(&[TokenType::If]) {
+            self.if_statement()
+        } else if self.match_token(&[TokenType::Print]) {
+            self.print_statement()
+        } else if self.match_token(&[TokenType::Return]) {
+            self.return_statement()
+        } else if self.match_token(&[TokenType::While]) {
+            self.while_statement()
+        } else if self.match_token(&[TokenType::LeftBrace]) {
+            Ok(Stmt::Block {
+                statements: self.block()?,
+            })
+        } else {
+            self.expression_statement()
+        }
+    }
+
+    // Called once the opening brace has matched
+    fn block(&mut self) -> Result<Vec<Stmt>> {
+        self.start_with_previous(NodeKind::Block);
+        let mut statements = vec![];
+
+        while !self.check_token(&TokenType::RightBrace) && !self.at_end() {
+            statements.push(self.declaration()?);
+        }
+
+        self.consume(TokenType::RightBrace, "Expect '}' after block")?;
+        self.finish();
+
+        Ok(statements)
+    }
+
+    fn for_statement(&mut self) -> Result<Stmt> {
+        self.start_with_previous(NodeKind::ForStmt);
+        self.consume(TokenType::LeftParen, "Expect '(' after 'for'")?;
+
+        let initializer = if self.match_token(&[TokenType::Semicolon]) {
+            None
+        } else if self.match_token(&[TokenType::Var]) {
+            Some(self.var_declaration()?)
+        } else {
+            Some(self.expression_statement()?)
+        };
+
+        let condition = if self.check_token(&TokenType::Semicolon) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(TokenType::Semicolon, "Expect ';' after loop condition")?;
+
+        let increment = if self.check_token(&TokenType::RightParen) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(TokenType::RightParen, "Expect ')' after for clauses")?;
+
+        let body = self.statement()?;
+        self.finish();
+
+        Ok(desugar_for(initializer, condition, increment, body))
+    }
+
+    fn if_statement(&mut self) -> Result<Stmt> {
+        self.start_with_previous(NodeKind::IfStmt);
+        self.consume(TokenType::LeftParen, "Expect '(' after 'if'")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after if condition")?;
+
+        let then_branch = Box::new(self.statement()?);
+        let else_branch = if self.match_token(&[TokenType::Else]) {
+            Some(Box::new(self.statement()?))
+        } else {
+            None
+        };
+        self.finish();
+
+        Ok(Stmt::If {
+            condition,
+            then_branch,
+            else_branch,
+        })
+    }
+
+    fn while_statement(&mut self) -> Result<Stmt> {
+        self.start_with_previous(NodeKind::WhileStmt);
+        self.consume(TokenType::LeftParen, "Expect '(' after 'while'")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after condition")?;
+
+        let body = Box::new(self.statement()?);
+        self.finish();
+
+        Ok(Stmt::While { condition, body })
+    }
+
+    fn print_statement(&mut self) -> Result<Stmt> {
+        self.start_with_previous(NodeKind::PrintStmt);
+        let expression = self.expression()?;
+        self.consume(TokenType::Semicolon, "Expect ';' after value")?;
+        self.finish();
+
+        Ok(Stmt::Print { expression })
+    }
+
+    fn return_statement(&mut self) -> Result<Stmt> {
+        self.start_with_previous(NodeKind::ReturnStmt);
+        let keyword = self.previous();
+
+        let value = if self.check_token(&TokenType::Semicolon) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+
+        self.consume(TokenType::Semicolon, "Expect ';' after return value")?;
+        self.finish();
+
+        Ok(Stmt::Return { keyword, value })
+    }
+
+    fn expression_statement(&mut self) -> Result<Stmt> {
+        self.start(NodeKind::ExprStmt);
+        let expression = self.expression()?;
+        self.consume(TokenType::Semicolon, "Expect ';' after expression")?;
+        self.finish();
+
+        Ok(Stmt::Expression { expression })
+    }
+
     fn expression(&mut self) -> Result<Expr> {
-        self.equality()
+        self.assignment()
+    }
+
+    fn assignment(&mut self) -> Result<Expr> {
+        let checkpoint = self.checkpoint();
+        let expr = self.or()?;
+
+        if self.match_token(&[TokenType::Equal]) {
+            self.start_at(checkpoint, NodeKind::Assign);
+            let equals = self.previous();
+            let value = self.assignment()?;
+            self.finish();
+
+            return match expr {
+                Expr::Variable { name, .. } => Ok(Expr::Assign {
+                    id: ExprId::new(),
+                    name,
+                    value: Box::new(value),
+                }),
+                Expr::Get { object, name } => Ok(Expr::Set {
+                    object,
+                    name,
+                    value: Box::new(value),
+                }),
+                _ => {
+                    let err = self.error(
+                        &equals,
+                        "E0103",
+                        "Invalid assignment target",
+                        "can't assign to the expression on the left",
+                    );
+                    self.errors.push(err);
+
+                    Ok(expr)
+                }
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        self.logical_op(&[TokenType::Or], |p| p.and())
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        self.logical_op(&[TokenType::And], |p| p.equality())
+    }
+
+    fn logical_op<F>(&mut self, ops: &[TokenType], mut right_fn: F) -> Result<Expr>
+    where
+        F: FnMut(&mut Self) -> Result<Expr>,
+    {
+        let checkpoint = self.checkpoint();
+        let mut expr = right_fn(self)?;
+
+        while self.match_token(ops) {
+            self.start_at(checkpoint, NodeKind::Logical);
+            let operator = self.previous();
+            let right = right_fn(self)?;
+            self.finish();
+            expr = Expr::Logical {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            };
+        }
+
+        Ok(expr)
     }
 
     fn binary_op<F>(&mut self, ops: &[TokenType], mut right_fn: F) -> Result<Expr>
     where
         F: FnMut(&mut Self) -> Result<Expr>,
     {
+        let checkpoint = self.checkpoint();
         let mut expr = right_fn(self)?;
 
         while self.match_token(ops) {
+            self.start_at(checkpoint, NodeKind::Binary);
             let operator = self.previous();
             let right = right_fn(self)?;
+            self.finish();
             expr = Expr::Binary {
                 left: Box::new(expr),
                 operator,
@@ -94,15 +484,78 @@ impl<'a> Parser<'a> {
 
     fn unary(&mut self) -> Result<Expr> {
         if self.match_token(&[TokenType::Bang, TokenType::Minus]) {
+            self.start_with_previous(NodeKind::Unary);
             let operator = self.previous();
             let right = self.unary()?;
+            self.finish();
+
             return Ok(Expr::Unary {
                 operator,
                 right: Box::new(right),
             });
         }
 
-        self.primary()
+        self.call()
+    }
+
+    fn call(&mut self) -> Result<Expr> {
+        let checkpoint = self.checkpoint();
+        let mut expr = self.primary()?;
+
+        loop {
+            if self.match_token(&[TokenType::LeftParen]) {
+                self.start_at(checkpoint, NodeKind::Call);
+                expr = self.finish_call(expr)?;
+                self.finish();
+            } else if self.match_token(&[TokenType::Dot]) {
+                self.start_at(checkpoint, NodeKind::Get);
+                let name = self.consume(TokenType::IDENTIFIER, "Expect property name after '.'")?;
+                self.finish();
+
+                expr = Expr::Get {
+                    object: Box::new(expr),
+                    name,
+                };
+            } else {
+                break;
+            }
+        }
+
+        Ok(expr)
+    }
+
+    fn finish_call(&mut self, callee: Expr) -> Result<Expr> {
+        self.start_with_previous(NodeKind::Args);
+        let mut arguments = vec![];
+
+        if !self.check_token(&TokenType::RightParen) {
+            loop {
+                if arguments.len() >= MAX_ARGS {
+                    let err = self.error(
+                        self.peek(),
+                        "E0105",
+                        &format!("Can't have more than {} arguments", MAX_ARGS),
+                        "one argument too many",
+                    );
+                    self.errors.push(err);
+                }
+
+                arguments.push(self.expression()?);
+
+                if !self.match_token(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+
+        let paren = self.consume(TokenType::RightParen, "Expect ')' after arguments")?;
+        self.finish();
+
+        Ok(Expr::Call {
+            callee: Box::new(callee),
+            paren,
+            arguments,
+        })
     }
 
     fn primary(&mut self) -> Result<Expr> {
@@ -119,39 +572,89 @@ impl<'a> Parser<'a> {
             TokenType::Number(num) => Expr::Literal {
                 value: Literal::Number(*num),
             },
-            TokenType::Stringy(num) => Expr::Literal {
-                value: Literal::Stringy(num.clone()),
+            TokenType::Stringy(value) => Expr::Literal {
+                value: Literal::Stringy(*value),
             },
+            TokenType::Identifier(_) => Expr::Variable {
+                id: ExprId::new(),
+                name: *self.peek(),
+            },
+            TokenType::This => Expr::This {
+                id: ExprId::new(),
+                keyword: *self.peek(),
+            },
+            TokenType::Super => {
+                let keyword = self.advance();
+                self.start_with_previous(NodeKind::Super);
+                self.consume(TokenType::Dot, "Expect '.' after 'super'")?;
+                let method =
+                    self.consume(TokenType::IDENTIFIER, "Expect superclass method name")?;
+                self.finish();
+
+                return Ok(Expr::Super {
+                    id: ExprId::new(),
+                    keyword,
+                    method,
+                });
+            }
             TokenType::LeftParen => {
+                self.advance();
+                self.start_with_previous(NodeKind::Grouping);
                 let exp = self.expression()?;
                 self.consume(TokenType::RightParen, "Expect ')' after expression")?;
+                self.finish();
 
-                Expr::Grouping {
+                return Ok(Expr::Grouping {
                     expression: Box::new(exp),
-                }
+                });
+            }
+            _ => {
+                return Err(self.error(
+                    self.peek(),
+                    "E0102",
+                    "Expect expression",
+                    "expected an expression",
+                ))
             }
-            token => return Err(ParseErr(format!("Encountered unexpected token: {}", token))),
         };
 
         self.advance();
 
+        // The rest are a single token, so their nodes close straight away
+        self.start_with_previous(match expr {
+            Expr::Variable { .. } => NodeKind::Variable,
+            Expr::This { .. } => NodeKind::This,
+            _ => NodeKind::Literal,
+        });
+        self.finish();
+
         Ok(expr)
     }
 
     fn consume(&mut self, token_type: TokenType, message: &str) -> Result<Token> {
         if self.check_token(&token_type) {
-            return Ok(self.advance().clone());
+            return Ok(self.advance());
         }
-        eprintln!("{}", message);
 
-        Err(ParseErr(format!(
-            "Error while consuming token: {}",
-            message
-        )))
+        // Messages all read "Expect ...", which the label repeats in lower case
+        let label = message.replacen("Expect", "expected", 1);
+
+        Err(self.error(self.peek(), "E0101", message, &label))
     }
 
+    fn error(&self, token: &Token, code: &'static str, message: &str, label: &str) -> Error {
+        ParseErr {
+            line: token.line,
+            span: token.span,
+            code,
+            message: message.to_string(),
+            label: label.to_string(),
+        }
+    }
+
+    // Tokens are `Copy`, so handing one out is as cheap as a reference
     fn previous(&self) -> Token {
-        self.tokens.get(self.current - 1).unwrap().clone()
+        self.tokens[self.current - 1]
     }
 
     fn check_token(&self, token_type: &TokenType) -> bool {
@@ -165,6 +668,10 @@ impl<'a> Parser<'a> {
     fn advance(&mut self) -> Token {
         if !self.at_end() {
             self.current += 1;
+
+            if let Some(cst) = &mut self.cst {
+                cst.token();
+            }
         }
 
         self.previous()
@@ -178,6 +685,37 @@ impl<'a> Parser<'a> {
         self.peek().token_type == TokenType::Eof
     }
 
+    // CST nodes are opened and closed around the tokens they cover. These do
+    // nothing unless the parser was made by `with_cst`.
+    fn checkpoint(&self) -> usize {
+        self.cst.as_ref().map_or(0, CstBuilder::checkpoint)
+    }
+
+    fn start(&mut self, kind: NodeKind) {
+        if let Some(cst) = &mut self.cst {
+            cst.start(kind);
+        }
+    }
+
+    fn start_at(&mut self, checkpoint: usize, kind: NodeKind) {
+        if let Some(cst) = &mut self.cst {
+            cst.start_at(checkpoint, kind);
+        }
+    }
+
+    // Most nodes are only known once their first token has matched
+    fn start_with_previous(&mut self, kind: NodeKind) {
+        if let Some(cst) = &mut self.cst {
+            cst.start_at(cst.checkpoint() - 1, kind);
+        }
+    }
+
+    fn finish(&mut self) {
+        if let Some(cst) = &mut self.cst {
+            cst.finish();
+        }
+    }
+
     fn match_token(&mut self, token_types: &[TokenType]) -> bool {
         if token_types
             .iter()
@@ -191,3 +729,104 @@ impl<'a> Parser<'a> {
         }
     }
 }
+
+// Desugars `for (init; cond; incr) body` into an equivalent `while` loop
+pub fn desugar_for(
+    initializer: Option<Stmt>,
+    condition: Option<Expr>,
+    increment: Option<Expr>,
+    mut body: Stmt,
+) -> Stmt {
+    if let Some(increment) = increment {
+        body = Stmt::Block {
+            statements: vec![
+                body,
+                Stmt::Expression {
+                    expression: increment,
+                },
+            ],
+        };
+    }
+
+    body = Stmt::While {
+        condition: condition.unwrap_or(Expr::Literal {
+            value: Literal::Bool(true),
+        }),
+        body: Box::new(body),
+    };
+
+    match initializer {
+        Some(initializer) => Stmt::Block {
+            statements: vec![initializer, body],
+        },
+        None => body,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::scanner::Scanner;
+
+    use super::*;
+
+    fn parse_errors(src: &str) -> Vec<(String, &'static str, String)> {
+        let tokens = Scanner::new(src.to_string()).scan_tokens().unwrap();
+
+        match Parser::new(&tokens).parse() {
+            Ok(_) => vec![],
+            Err(errors) => errors
+                .iter()
+                .map(|err| {
+                    let diagnostic = err.diagnostic();
+                    (
+                        err.to_string(),
+                        diagnostic.code,
+                        diagnostic.labels[0].message.clone(),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn check_error_recovery() {
+        assert_eq!(
+            parse_errors(
+                "var = 1;
+                print (1;
+                var ok = 2;
+                1 = ok;
+                fun f( { }
+                print ok"
+            ),
+            [
+                (
+                    "[line 1] Parse error: Expect variable name".to_string(),
+                    "E0101",
+                    "expected variable name".to_string()
+                ),
+                (
+                    "[line 2] Parse error: Expect ')' after expression".to_string(),
+                    "E0101",
+                    "expected ')' after expression".to_string()
+                ),
+                (
+                    "[line 4] Parse error: Invalid assignment target".to_string(),
+                    "E0103",
+                    "can't assign to the expression on the left".to_string()
+                ),
+                (
+                    "[line 5] Parse error: Expect parameter name".to_string(),
+                    "E0101",
+                    "expected parameter name".to_string()
+                ),
+                (
+                    "[line 6] Parse error: Expect ';' after value".to_string(),
+                    "E0101",
+                    "expected ';' after value".to_string()
+                ),
+            ]
+        );
+        assert!(parse_errors("var ok = 1; print ok;").is_empty());
+    }
+}
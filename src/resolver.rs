@@ -0,0 +1,370 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    ast::{Expr, ExprId, FunctionDecl, Stmt},
+    error::Error::{self, ResolveErr},
+    symbol::Symbol,
+    token::Token,
+    visitor::{ExprVisitor, StmtVisitor},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FunctionType {
+    None,
+    Function,
+    Initializer,
+    Method,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ClassType {
+    None,
+    Class,
+    Subclass,
+}
+
+pub struct Resolver {
+    // Scope depth of every local variable reference, keyed by expression
+    locals: HashMap<ExprId, usize>,
+    // Each scope maps a name to whether its initializer has finished resolving
+    scopes: Vec<HashMap<Symbol, bool>>,
+    current_function: FunctionType,
+    current_class: ClassType,
+    errors: Vec<Error>,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self {
+            locals: HashMap::new(),
+            scopes: vec![],
+            current_function: FunctionType::None,
+            current_class: ClassType::None,
+            errors: vec![],
+        }
+    }
+
+    pub fn resolve(mut self, statements: &[Stmt]) -> Result<HashMap<ExprId, usize>, Vec<Error>> {
+        statements
+            .iter()
+            .for_each(|statement| self.visit_stmt(statement));
+
+        if self.errors.is_empty() {
+            Ok(self.locals)
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    fn resolve_function(&mut self, declaration: &FunctionDecl, function_type: FunctionType) {
+        let enclosing_function = self.current_function;
+        self.current_function = function_type;
+
+        self.begin_scope();
+
+        declaration.params.iter().for_each(|param| {
+            self.declare(param);
+            self.define(param);
+        });
+        declaration
+            .body
+            .iter()
+            .for_each(|statement| self.visit_stmt(statement));
+
+        self.end_scope();
+
+        self.current_function = enclosing_function;
+    }
+
+    // Unresolved names are assumed to be globals
+    fn resolve_local(&mut self, id: ExprId, name: &Token) {
+        if let Some(depth) = self
+            .scopes
+            .iter()
+            .rev()
+            .position(|scope| scope.contains_key(&name.symbol()))
+        {
+            self.locals.insert(id, depth);
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &Token) {
+        let Some(scope) = self.scopes.last_mut() else {
+            return;
+        };
+
+        if scope.insert(name.symbol(), false).is_some() {
+            self.error(
+                name,
+                "E0201",
+                "Already a variable with this name in this scope",
+                "already declared in this scope",
+            );
+        }
+    }
+
+    fn define(&mut self, name: &Token) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.symbol(), true);
+        }
+    }
+
+    fn error(&mut self, token: &Token, code: &'static str, message: &str, label: &str) {
+        self.errors.push(ResolveErr {
+            line: token.line,
+            span: token.span,
+            code,
+            message: message.to_string(),
+            label: label.to_string(),
+        });
+    }
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Only the nodes that bind or look up names need anything beyond the
+// default walk
+impl StmtVisitor<()> for Resolver {
+    fn visit_child_expr(&mut self, expr: &Expr) {
+        self.visit_expr(expr)
+    }
+
+    fn visit_block(&mut self, statements: &[Stmt]) {
+        self.begin_scope();
+        statements
+            .iter()
+            .for_each(|statement| self.visit_stmt(statement));
+        self.end_scope();
+    }
+
+    fn visit_class(
+        &mut self,
+        name: &Token,
+        superclass: Option<&Expr>,
+        methods: &[Rc<FunctionDecl>],
+    ) {
+        let enclosing_class = self.current_class;
+        self.current_class = ClassType::Class;
+
+        self.declare(name);
+        self.define(name);
+
+        if let Some(superclass) = superclass {
+            if let Expr::Variable {
+                name: superclass_name,
+                ..
+            } = superclass
+            {
+                if superclass_name.symbol() == name.symbol() {
+                    self.error(
+                        superclass_name,
+                        "E0205",
+                        "A class can't inherit from itself",
+                        "names the class being declared",
+                    );
+                }
+            }
+
+            self.current_class = ClassType::Subclass;
+            self.visit_expr(superclass);
+
+            self.begin_scope();
+            self.scopes
+                .last_mut()
+                .expect("Superclass scope was just pushed")
+                .insert(Symbol::SUPER, true);
+        }
+
+        self.begin_scope();
+        self.scopes
+            .last_mut()
+            .expect("Class scope was just pushed")
+            .insert(Symbol::THIS, true);
+
+        methods.iter().for_each(|method| {
+            let function_type = if method.name.symbol() == Symbol::INIT {
+                FunctionType::Initializer
+            } else {
+                FunctionType::Method
+            };
+
+            self.resolve_function(method, function_type);
+        });
+
+        self.end_scope();
+
+        if superclass.is_some() {
+            self.end_scope();
+        }
+
+        self.current_class = enclosing_class;
+    }
+
+    fn visit_function(&mut self, declaration: &Rc<FunctionDecl>) {
+        self.declare(&declaration.name);
+        self.define(&declaration.name);
+
+        self.resolve_function(declaration, FunctionType::Function);
+    }
+
+    fn visit_return(&mut self, keyword: &Token, value: Option<&Expr>) {
+        if self.current_function == FunctionType::None {
+            self.error(
+                keyword,
+                "E0203",
+                "Can't return from top-level code",
+                "not inside a function",
+            );
+        }
+
+        if let Some(value) = value {
+            if self.current_function == FunctionType::Initializer {
+                self.error(
+                    keyword,
+                    "E0204",
+                    "Can't return a value from an initializer",
+                    "initializers always return 'this'",
+                );
+            }
+
+            self.visit_expr(value);
+        }
+    }
+
+    fn visit_var(&mut self, name: &Token, initializer: Option<&Expr>) {
+        self.declare(name);
+
+        if let Some(initializer) = initializer {
+            self.visit_expr(initializer);
+        }
+
+        self.define(name);
+    }
+}
+
+impl ExprVisitor<()> for Resolver {
+    fn visit_assign(&mut self, id: ExprId, name: &Token, value: &Expr) {
+        self.visit_expr(value);
+        self.resolve_local(id, name);
+    }
+
+    fn visit_super(&mut self, id: ExprId, keyword: &Token, _method: &Token) {
+        match self.current_class {
+            ClassType::None => self.error(
+                keyword,
+                "E0207",
+                "Can't use 'super' outside of a class",
+                "not inside a class",
+            ),
+            ClassType::Class => self.error(
+                keyword,
+                "E0206",
+                "Can't use 'super' in a class with no superclass",
+                "this class has no superclass",
+            ),
+            ClassType::Subclass => self.resolve_local(id, keyword),
+        }
+    }
+
+    fn visit_this(&mut self, id: ExprId, keyword: &Token) {
+        if self.current_class == ClassType::None {
+            self.error(
+                keyword,
+                "E0208",
+                "Can't use 'this' outside of a class",
+                "not inside a class",
+            );
+            return;
+        }
+
+        self.resolve_local(id, keyword);
+    }
+
+    fn visit_variable(&mut self, id: ExprId, name: &Token) {
+        let in_own_initializer = self
+            .scopes
+            .last()
+            .and_then(|scope| scope.get(&name.symbol()))
+            .is_some_and(|defined| !defined);
+
+        if in_own_initializer {
+            self.error(
+                name,
+                "E0202",
+                "Can't read local variable in its own initializer",
+                "read before it has a value",
+            );
+        }
+
+        self.resolve_local(id, name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parser::Parser, scanner::Scanner};
+
+    use super::*;
+
+    fn resolve(src: &str) -> Result<HashMap<ExprId, usize>, Vec<String>> {
+        let tokens = Scanner::new(src.to_string()).scan_tokens().unwrap();
+        let statements = Parser::new(&tokens).parse().unwrap();
+
+        Resolver::new().resolve(&statements).map_err(|errors| {
+            errors
+                .iter()
+                .map(|err| format!("{} {}", err.diagnostic().code, err))
+                .collect()
+        })
+    }
+
+    #[test]
+    fn check_static_errors() {
+        assert!(resolve("var a = 1; { var b = a; fun f() { return b; } }").is_ok());
+
+        assert_eq!(
+            resolve("{ var a = 1;\nvar a = 2; }").unwrap_err(),
+            ["E0201 [line 2] Resolve error: Already a variable with this name in this scope"]
+        );
+        assert_eq!(
+            resolve("var a = 1; { var a = a; }").unwrap_err(),
+            ["E0202 [line 1] Resolve error: Can't read local variable in its own initializer"]
+        );
+        assert_eq!(
+            resolve("return 1;").unwrap_err(),
+            ["E0203 [line 1] Resolve error: Can't return from top-level code"]
+        );
+        assert_eq!(
+            resolve("class A { init() {\nreturn 1; } }").unwrap_err(),
+            ["E0204 [line 2] Resolve error: Can't return a value from an initializer"]
+        );
+        assert!(resolve("class A { init() { return; } m() { return this; } }").is_ok());
+        assert_eq!(
+            resolve("class A < A {}").unwrap_err(),
+            ["E0205 [line 1] Resolve error: A class can't inherit from itself"]
+        );
+        assert_eq!(
+            resolve("class A { m() { super.m(); } }").unwrap_err(),
+            ["E0206 [line 1] Resolve error: Can't use 'super' in a class with no superclass"]
+        );
+        assert_eq!(
+            resolve("print this; super.foo();").unwrap_err(),
+            [
+                "E0208 [line 1] Resolve error: Can't use 'this' outside of a class",
+                "E0207 [line 1] Resolve error: Can't use 'super' outside of a class"
+            ]
+        );
+    }
+}
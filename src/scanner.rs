@@ -1,31 +1,66 @@
-use crate::token::{Token, TokenType};
+use crate::{
+    diagnostic::Diagnostic,
+    symbol::Symbol,
+    token::{Span, Token, TokenType},
+};
 
 pub struct Scanner {
     src: String,
     start: usize,
     current: usize,
     line: usize,
-    pub tokens: Vec<Token>,
+    // Column of the char at `current`, kept up to date as chars are consumed
+    column: usize,
+    // Position of the token being scanned, since strings can span lines
+    start_line: usize,
+    start_column: usize,
+    tokens: Vec<Token>,
+    diagnostics: Vec<Diagnostic>,
 }
 
 impl Scanner {
     pub fn new(src: String) -> Self {
+        Self::with_start(src, 0, 1)
+    }
+
+    // Scans `src` from byte `offset`, the start of line `line`. The REPL scans
+    // each new line this way so its spans index the whole session.
+    pub fn with_start(src: String, offset: usize, line: usize) -> Self {
         Self {
-            src: src.to_string(),
-            start: 0,
-            current: 0,
-            line: 1,
+            src,
+            start: offset,
+            current: offset,
+            line,
+            column: 1,
+            start_line: line,
+            start_column: 1,
             tokens: vec![],
+            diagnostics: vec![],
         }
     }
 
-    pub fn scan_tokens(&mut self) {
+    // Scans the whole source, reporting every lexical error rather than
+    // stopping at the first
+    pub fn scan_tokens(mut self) -> Result<Vec<Token>, Vec<Diagnostic>> {
         while !self.at_end() {
             self.start = self.current;
+            self.start_line = self.line;
+            self.start_column = self.column;
             self.scan_token()
         }
 
-        self.tokens.push(Token::new(TokenType::Eof, self.line));
+        self.tokens.push(Token::new(
+            TokenType::Eof,
+            Span::new(self.current, self.current),
+            self.line,
+            self.column,
+        ));
+
+        if self.diagnostics.is_empty() {
+            Ok(self.tokens)
+        } else {
+            Err(self.diagnostics)
+        }
     }
 
     fn scan_token(&mut self) {
@@ -79,11 +114,13 @@ impl Scanner {
                     while self.peek() != '\n' && !self.at_end() {
                         self.advance();
                     }
+
+                    self.add_token(TokenType::Comment)
                 } else {
                     self.add_token(TokenType::Slash)
                 }
             }
-            '\n' => self.line += 1,
+            '\n' => self.newline(),
             ' ' | '\r' | '\t' => {}
             '"' => self.handle_string(),
             _ => {
@@ -92,7 +129,15 @@ impl Scanner {
                 } else if c.is_ascii_alphabetic() {
                     self.handle_identifier();
                 } else {
-                    eprintln!("Unexpected character on line {}: {}", self.line, c);
+                    let mut diagnostic =
+                        Diagnostic::error("E0001", format!("Unexpected character '{}'", c))
+                            .with_label(self.span(), "not valid in Lox source");
+
+                    if c == '\'' {
+                        diagnostic = diagnostic.with_help("strings are written in double quotes");
+                    }
+
+                    self.diagnostics.push(diagnostic);
                 }
             }
         }
@@ -102,86 +147,81 @@ impl Scanner {
         self.current >= self.src.len()
     }
 
+    // `start` and `current` are byte offsets that always sit on char boundaries
     fn advance(&mut self) -> char {
-        let ch = self
-            .src
-            .chars()
-            .nth(self.current)
-            .expect("Error while peeking in advance()...");
-        self.current += 1;
+        let ch = self.peek();
+        self.current += ch.len_utf8();
+        self.column += 1;
 
         ch
     }
 
     fn add_token(&mut self, token_type: TokenType) {
-        self.tokens.push(Token::new(token_type, self.line))
+        self.tokens.push(Token::new(
+            token_type,
+            self.span(),
+            self.start_line,
+            self.start_column,
+        ))
     }
 
-    fn match_advance(&mut self, expected: char) -> bool {
-        if self.at_end() {
-            return false;
-        }
+    fn span(&self) -> Span {
+        Span::new(self.start, self.current)
+    }
 
-        if self
-            .src
-            .chars()
-            .nth(self.current)
-            .expect("Error while peeking in match_advance()...")
-            != expected
-        {
+    // Called after consuming a '\n'
+    fn newline(&mut self) {
+        self.line += 1;
+        self.column = 1;
+    }
+
+    fn match_advance(&mut self, expected: char) -> bool {
+        if self.at_end() || self.peek() != expected {
             return false;
         }
 
-        self.current += 1;
+        self.advance();
 
         true
     }
 
     fn peek(&self) -> char {
-        if self.at_end() {
-            return '\0';
-        }
-
-        self.src
-            .chars()
-            .nth(self.current)
-            .expect("Error while peeking in peek()...")
+        self.src[self.current..].chars().next().unwrap_or('\0')
     }
 
     fn peek_next(&self) -> char {
-        if self.current + 1 >= self.src.len() {
-            return '\0';
-        }
+        let mut chars = self.src[self.current..].chars();
+        chars.next();
 
-        self.src
-            .chars()
-            .nth(self.current + 1)
-            .expect("Error while peeking in peek_next()...")
+        chars.next().unwrap_or('\0')
     }
 
     fn handle_string(&mut self) {
         while self.peek() != '"' && !self.at_end() {
-            if self.peek() == '\n' {
-                self.line += 1
+            if self.advance() == '\n' {
+                self.newline();
             }
-
-            self.advance();
         }
 
         if self.at_end() {
-            eprintln!("Unterminated string on line {}", self.line);
+            self.diagnostics.push(
+                Diagnostic::error("E0002", "Unterminated string")
+                    .with_label(Span::new(self.current, self.current), "source ends here")
+                    .with_secondary_label(
+                        Span::new(self.start, self.start + 1),
+                        "string starts here",
+                    )
+                    .with_help("add a closing '\"'"),
+            );
             return;
         }
 
         self.advance();
 
-        let value = self
-            .src
-            .get(self.start + 1..self.current - 1)
-            .unwrap_or_default()
-            .to_string();
+        // The quotes are single bytes, so trimming them keeps char boundaries
+        let value = &self.src[self.start + 1..self.current - 1];
 
-        self.add_token(TokenType::Stringy(value));
+        self.add_token(TokenType::Stringy(Symbol::intern(value)));
     }
 
     fn handle_number(&mut self) {
@@ -199,7 +239,7 @@ impl Scanner {
         }
 
         self.add_token(TokenType::Number(
-            self.src.chars().as_str()[self.start..self.current]
+            self.src[self.start..self.current]
                 .parse::<f64>()
                 .expect("Error while parsing number..."),
         ));
@@ -210,14 +250,124 @@ impl Scanner {
             self.advance();
         }
 
-        let text = &self.src.as_str()[self.start..self.current];
+        let text = &self.src[self.start..self.current];
 
         if let Some(token_type) = TokenType::get_keyword(text) {
-            self.add_token(token_type.clone());
+            self.add_token(*token_type);
         } else {
-            self.add_token(TokenType::Identifier(
-                self.src.as_str()[self.start..self.current].to_string(),
-            ))
+            self.add_token(TokenType::Identifier(Symbol::intern(text)))
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan(src: &str) -> Vec<TokenType> {
+        Scanner::new(src.to_string())
+            .scan_tokens()
+            .unwrap()
+            .into_iter()
+            .map(|token| token.token_type)
+            .collect()
+    }
+
+    #[test]
+    fn check_multibyte_strings() {
+        let tokens = scan("print \"héllo, 世界 🦀\";");
+
+        assert_eq!(tokens.len(), 4);
+        let TokenType::Stringy(value) = tokens[1] else {
+            panic!("Expected a string but found {}", tokens[1]);
+        };
+        assert_eq!(value.as_str(), "héllo, 世界 🦀");
+    }
+
+    #[test]
+    fn check_multibyte_comments() {
+        let src = "// ünïcödé ✓ comment\nvar x = 1; // 🦀";
+        let tokens = Scanner::new(src.to_string()).scan_tokens().unwrap();
+
+        let types: Vec<String> = tokens
+            .iter()
+            .map(|token| token.token_type.to_string())
+            .collect();
+
+        assert_eq!(
+            types,
+            [
+                "comment",
+                "var",
+                "Identifier(x)",
+                "=",
+                "Number(1)",
+                ";",
+                "comment",
+                "EOF"
+            ]
+        );
+        assert_eq!(tokens[0].lexeme(src), "// ünïcödé ✓ comment");
+        assert_eq!(tokens[6].lexeme(src), "// 🦀");
+        assert!(tokens[1..].iter().all(|token| token.line == 2));
+    }
+
+    #[test]
+    fn check_spans() {
+        let src = "var s = \"é\nb\";\n  print \"ü\" + s;";
+        let tokens = Scanner::new(src.to_string()).scan_tokens().unwrap();
+
+        let positions: Vec<(&str, usize, usize, usize, usize)> = tokens
+            .iter()
+            .map(|token| {
+                (
+                    token.lexeme(src),
+                    token.span.start,
+                    token.span.end,
+                    token.line,
+                    token.column,
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            positions,
+            [
+                ("var", 0, 3, 1, 1),
+                ("s", 4, 5, 1, 5),
+                ("=", 6, 7, 1, 7),
+                ("\"é\nb\"", 8, 14, 1, 9),
+                (";", 14, 15, 2, 3),
+                ("print", 18, 23, 3, 3),
+                ("\"ü\"", 24, 28, 3, 9),
+                ("+", 29, 30, 3, 13),
+                ("s", 31, 32, 3, 15),
+                (";", 32, 33, 3, 16),
+                ("", 33, 33, 3, 17),
+            ]
+        );
+    }
+
+    #[test]
+    fn check_lexical_errors() {
+        let Err(diagnostics) = Scanner::new("var a = @;\nprint # \"open".to_string()).scan_tokens()
+        else {
+            panic!("Expected lexical errors");
         };
+
+        let errors: Vec<(&str, &str)> = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.code, diagnostic.message.as_str()))
+            .collect();
+
+        assert_eq!(
+            errors,
+            [
+                ("E0001", "Unexpected character '@'"),
+                ("E0001", "Unexpected character '#'"),
+                ("E0002", "Unterminated string"),
+            ]
+        );
+        assert_eq!(diagnostics[1].labels[0].span, Span::new(17, 18));
     }
 }
@@ -0,0 +1,107 @@
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Display},
+    sync::{LazyLock, Mutex},
+};
+
+// Handle to a string in the global interner. Equal strings always intern to
+// the same symbol, so comparing and hashing symbols never touches the text.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+// Names the interpreters look up by hand are interned up front at fixed slots
+const PREDEFINED: [&str; 4] = ["", "this", "super", "init"];
+
+impl Symbol {
+    pub const EMPTY: Symbol = Symbol(0);
+    pub const THIS: Symbol = Symbol(1);
+    pub const SUPER: Symbol = Symbol(2);
+    pub const INIT: Symbol = Symbol(3);
+
+    pub fn intern(value: &str) -> Self {
+        INTERNER.lock().unwrap().intern(value)
+    }
+
+    // Takes the interner's lock, so hot paths should compare symbols and only
+    // read the text when it's needed for output
+    pub fn as_str(self) -> &'static str {
+        INTERNER.lock().unwrap().strings[self.0 as usize]
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+// Serialized as the text itself, since handles mean nothing outside the process
+#[cfg(feature = "serde")]
+impl serde::Serialize for Symbol {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+// Interned strings are leaked so `as_str` can hand out `'static` borrows, and
+// nothing is ever removed. That's cheap for identifiers and literals, but the
+// tree-walker interns every string it builds at runtime too, so a program or
+// REPL session that keeps making new strings grows the table for as long as
+// the process lives. The VM keeps its own string table, which the GC sweeps.
+struct Interner {
+    symbols: HashMap<&'static str, Symbol>,
+    strings: Vec<&'static str>,
+}
+
+impl Interner {
+    fn intern(&mut self, value: &str) -> Symbol {
+        if let Some(symbol) = self.symbols.get(value) {
+            return *symbol;
+        }
+
+        let value: &'static str = Box::leak(value.into());
+        let symbol = Symbol(self.strings.len() as u32);
+
+        self.strings.push(value);
+        self.symbols.insert(value, symbol);
+
+        symbol
+    }
+}
+
+static INTERNER: LazyLock<Mutex<Interner>> = LazyLock::new(|| {
+    let mut interner = Interner {
+        symbols: HashMap::new(),
+        strings: vec![],
+    };
+
+    PREDEFINED.iter().for_each(|value| {
+        interner.intern(value);
+    });
+
+    Mutex::new(interner)
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_intern() {
+        let a = Symbol::intern("interned");
+        let b = Symbol::intern(&(String::from("inter") + "ned"));
+
+        assert_eq!(a, b);
+        assert_ne!(a, Symbol::intern("other"));
+        assert_eq!(a.as_str(), "interned");
+        assert_eq!(Symbol::intern("this"), Symbol::THIS);
+        assert_eq!(Symbol::intern("super"), Symbol::SUPER);
+        assert_eq!(Symbol::intern("init"), Symbol::INIT);
+    }
+}
@@ -1,18 +1,75 @@
 use std::{collections::HashMap, fmt::Display, sync::LazyLock};
 
-#[derive(Debug, Clone)]
+use crate::symbol::Symbol;
+
+// Half-open range of byte offsets into the source
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    // Whether the span is a valid slice of `src`
+    pub fn within(&self, src: &str) -> bool {
+        src.get(self.start..self.end).is_some()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Token {
     pub token_type: TokenType,
+    pub span: Span,
+    // Line and column (counted in chars, from 1) where the token starts
     pub line: usize,
+    pub column: usize,
 }
 
 impl Token {
-    pub fn new(token_type: TokenType, line: usize) -> Self {
-        Self { token_type, line }
+    pub fn new(token_type: TokenType, span: Span, line: usize, column: usize) -> Self {
+        Self {
+            token_type,
+            span,
+            line,
+            column,
+        }
+    }
+
+    // Token the interpreters make up rather than read from source, like the
+    // `this` used to look up a bound receiver
+    pub fn synthetic(token_type: TokenType, line: usize) -> Self {
+        Self::new(token_type, Span::default(), line, 0)
+    }
+
+    // Exact source text, including the quotes around string literals. Only
+    // the span is kept, so this needs the source the token was scanned from.
+    pub fn lexeme<'a>(&self, src: &'a str) -> &'a str {
+        &src[self.span.start..self.span.end]
+    }
+
+    pub fn symbol(&self) -> Symbol {
+        match self.token_type {
+            TokenType::Identifier(name) => name,
+            TokenType::This => Symbol::THIS,
+            TokenType::Super => Symbol::SUPER,
+            token_type => unreachable!("Token {} has no name", token_type),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.symbol().as_str()
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", content = "value"))]
 pub enum TokenType {
     // Single-character tokens.
     LeftParen,
@@ -38,8 +95,8 @@ pub enum TokenType {
     LessEqual,
 
     // Literals.
-    Identifier(String),
-    Stringy(String),
+    Identifier(Symbol),
+    Stringy(Symbol),
     Number(f64),
 
     // Keywords.
@@ -60,6 +117,10 @@ pub enum TokenType {
     Var,
     While,
 
+    // Trivia. Kept so tools like the formatter can reproduce comments; the
+    // parser skips it.
+    Comment,
+
     Eof,
 }
 
@@ -112,6 +173,9 @@ impl Display for TokenType {
             TokenType::Var => write!(f, "var"),
             TokenType::While => write!(f, "while"),
 
+            // Trivia.
+            TokenType::Comment => write!(f, "comment"),
+
             // End of file.
             TokenType::Eof => write!(f, "EOF"),
         }
@@ -119,6 +183,9 @@ impl Display for TokenType {
 }
 
 impl TokenType {
+    // Pattern for `consume`/`check_token`, which ignore the interned name
+    pub const IDENTIFIER: TokenType = TokenType::Identifier(Symbol::EMPTY);
+
     pub fn get_keyword(keyword: &str) -> Option<&Self> {
         KEYWORDS.get(keyword)
     }
@@ -164,6 +231,7 @@ impl PartialEq for TokenType {
                 | (TokenType::True, TokenType::True)
                 | (TokenType::Var, TokenType::Var)
                 | (TokenType::While, TokenType::While)
+                | (TokenType::Comment, TokenType::Comment)
                 | (TokenType::Eof, TokenType::Eof)
                 | (TokenType::Identifier(_), TokenType::Identifier(_))
                 | (TokenType::Stringy(_), TokenType::Stringy(_))
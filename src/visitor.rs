@@ -0,0 +1,469 @@
+use std::{iter::once, ops::ControlFlow, rc::Rc};
+
+use crate::{
+    ast::{Expr, ExprId, FunctionDecl, Literal, Stmt},
+    token::Token,
+};
+
+// What a visit returns. The default methods walk a node's children in source
+// order, handing back the first result that asks to stop (an error, say) and
+// `output()` once every child is done.
+pub trait VisitResult {
+    fn output() -> Self;
+
+    fn is_break(&self) -> bool {
+        false
+    }
+}
+
+impl VisitResult for () {
+    fn output() -> Self {}
+}
+
+impl VisitResult for String {
+    fn output() -> Self {
+        String::new()
+    }
+}
+
+impl<T: VisitResult, E> VisitResult for Result<T, E> {
+    fn output() -> Self {
+        Ok(T::output())
+    }
+
+    fn is_break(&self) -> bool {
+        self.as_ref().map_or(true, T::is_break)
+    }
+}
+
+impl<B> VisitResult for ControlFlow<B> {
+    fn output() -> Self {
+        ControlFlow::Continue(())
+    }
+
+    fn is_break(&self) -> bool {
+        ControlFlow::is_break(self)
+    }
+}
+
+// Returns early from a walk when a child's result breaks
+macro_rules! try_visit {
+    ($visit:expr) => {
+        let result = $visit;
+
+        if result.is_break() {
+            return result;
+        }
+    };
+}
+
+// One method per expression kind, each handed that variant's fields.
+// `visit_expr` dispatches to them and is what passes call to recurse. Every
+// method defaults to walking the node's children, so a pass only overrides
+// the kinds it cares about.
+pub trait ExprVisitor<R: VisitResult> {
+    fn visit_expr(&mut self, expr: &Expr) -> R {
+        walk_expr(self, expr)
+    }
+
+    fn visit_assign(&mut self, _id: ExprId, _name: &Token, value: &Expr) -> R {
+        walk_exprs(self, [value])
+    }
+
+    fn visit_binary(&mut self, left: &Expr, _operator: &Token, right: &Expr) -> R {
+        walk_exprs(self, [left, right])
+    }
+
+    fn visit_call(&mut self, callee: &Expr, _paren: &Token, arguments: &[Expr]) -> R {
+        walk_exprs(self, once(callee).chain(arguments))
+    }
+
+    fn visit_get(&mut self, object: &Expr, _name: &Token) -> R {
+        walk_exprs(self, [object])
+    }
+
+    fn visit_grouping(&mut self, expression: &Expr) -> R {
+        walk_exprs(self, [expression])
+    }
+
+    fn visit_literal(&mut self, _value: &Literal) -> R {
+        R::output()
+    }
+
+    fn visit_logical(&mut self, left: &Expr, _operator: &Token, right: &Expr) -> R {
+        walk_exprs(self, [left, right])
+    }
+
+    fn visit_set(&mut self, object: &Expr, _name: &Token, value: &Expr) -> R {
+        walk_exprs(self, [object, value])
+    }
+
+    fn visit_super(&mut self, _id: ExprId, _keyword: &Token, _method: &Token) -> R {
+        R::output()
+    }
+
+    fn visit_this(&mut self, _id: ExprId, _keyword: &Token) -> R {
+        R::output()
+    }
+
+    fn visit_unary(&mut self, _operator: &Token, right: &Expr) -> R {
+        walk_exprs(self, [right])
+    }
+
+    fn visit_variable(&mut self, _id: ExprId, _name: &Token) -> R {
+        R::output()
+    }
+}
+
+pub fn walk_expr<R: VisitResult, V: ExprVisitor<R> + ?Sized>(visitor: &mut V, expr: &Expr) -> R {
+    match expr {
+        Expr::Assign { id, name, value } => visitor.visit_assign(*id, name, value),
+        Expr::Binary {
+            left,
+            operator,
+            right,
+        } => visitor.visit_binary(left, operator, right),
+        Expr::Call {
+            callee,
+            paren,
+            arguments,
+        } => visitor.visit_call(callee, paren, arguments),
+        Expr::Get { object, name } => visitor.visit_get(object, name),
+        Expr::Grouping { expression } => visitor.visit_grouping(expression),
+        Expr::Literal { value } => visitor.visit_literal(value),
+        Expr::Logical {
+            left,
+            operator,
+            right,
+        } => visitor.visit_logical(left, operator, right),
+        Expr::Set {
+            object,
+            name,
+            value,
+        } => visitor.visit_set(object, name, value),
+        Expr::Super {
+            id,
+            keyword,
+            method,
+        } => visitor.visit_super(*id, keyword, method),
+        Expr::This { id, keyword } => visitor.visit_this(*id, keyword),
+        Expr::Unary { operator, right } => visitor.visit_unary(operator, right),
+        Expr::Variable { id, name } => visitor.visit_variable(*id, name),
+    }
+}
+
+pub fn walk_exprs<'a, R: VisitResult, V: ExprVisitor<R> + ?Sized>(
+    visitor: &mut V,
+    exprs: impl IntoIterator<Item = &'a Expr>,
+) -> R {
+    for expr in exprs {
+        try_visit!(visitor.visit_expr(expr));
+    }
+
+    R::output()
+}
+
+// Statement counterpart of `ExprVisitor`. Expressions inside statements go
+// through `visit_child_expr`, since a pass may give them a different result
+// type than statements, as the interpreter does with values.
+pub trait StmtVisitor<R: VisitResult> {
+    fn visit_stmt(&mut self, stmt: &Stmt) -> R {
+        walk_stmt(self, stmt)
+    }
+
+    fn visit_child_expr(&mut self, expr: &Expr) -> R;
+
+    fn visit_block(&mut self, statements: &[Stmt]) -> R {
+        walk_stmts(self, statements)
+    }
+
+    fn visit_class(
+        &mut self,
+        _name: &Token,
+        superclass: Option<&Expr>,
+        methods: &[Rc<FunctionDecl>],
+    ) -> R {
+        if let Some(superclass) = superclass {
+            try_visit!(self.visit_child_expr(superclass));
+        }
+
+        for method in methods {
+            try_visit!(walk_stmts(self, &method.body));
+        }
+
+        R::output()
+    }
+
+    fn visit_expression(&mut self, expression: &Expr) -> R {
+        self.visit_child_expr(expression)
+    }
+
+    fn visit_function(&mut self, declaration: &Rc<FunctionDecl>) -> R {
+        walk_stmts(self, &declaration.body)
+    }
+
+    fn visit_if(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: Option<&Stmt>) -> R {
+        try_visit!(self.visit_child_expr(condition));
+
+        walk_stmts(self, once(then_branch).chain(else_branch))
+    }
+
+    fn visit_print(&mut self, expression: &Expr) -> R {
+        self.visit_child_expr(expression)
+    }
+
+    fn visit_return(&mut self, _keyword: &Token, value: Option<&Expr>) -> R {
+        match value {
+            Some(value) => self.visit_child_expr(value),
+            None => R::output(),
+        }
+    }
+
+    fn visit_var(&mut self, _name: &Token, initializer: Option<&Expr>) -> R {
+        match initializer {
+            Some(initializer) => self.visit_child_expr(initializer),
+            None => R::output(),
+        }
+    }
+
+    fn visit_while(&mut self, condition: &Expr, body: &Stmt) -> R {
+        try_visit!(self.visit_child_expr(condition));
+
+        self.visit_stmt(body)
+    }
+}
+
+pub fn walk_stmt<R: VisitResult, V: StmtVisitor<R> + ?Sized>(visitor: &mut V, stmt: &Stmt) -> R {
+    match stmt {
+        Stmt::Block { statements } => visitor.visit_block(statements),
+        Stmt::Class {
+            name,
+            superclass,
+            methods,
+        } => visitor.visit_class(name, superclass.as_ref(), methods),
+        Stmt::Expression { expression } => visitor.visit_expression(expression),
+        Stmt::Function(declaration) => visitor.visit_function(declaration),
+        Stmt::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => visitor.visit_if(condition, then_branch, else_branch.as_deref()),
+        Stmt::Print { expression } => visitor.visit_print(expression),
+        Stmt::Return { keyword, value } => visitor.visit_return(keyword, value.as_ref()),
+        Stmt::Var { name, initializer } => visitor.visit_var(name, initializer.as_ref()),
+        Stmt::While { condition, body } => visitor.visit_while(condition, body),
+    }
+}
+
+pub fn walk_stmts<'a, R: VisitResult, V: StmtVisitor<R> + ?Sized>(
+    visitor: &mut V,
+    statements: impl IntoIterator<Item = &'a Stmt>,
+) -> R {
+    for statement in statements {
+        try_visit!(visitor.visit_stmt(statement));
+    }
+
+    R::output()
+}
+
+// Rewrites a tree in place. Both methods default to walking into the
+// children, so a pass only overrides them to replace the nodes it cares about
+// and calls the walk functions to keep descending.
+pub trait VisitorMut {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr)
+    }
+
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        walk_stmt_mut(self, stmt)
+    }
+}
+
+pub fn walk_expr_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expr: &mut Expr) {
+    match expr {
+        Expr::Assign { value, .. } | Expr::Grouping { expression: value } => {
+            visitor.visit_expr_mut(value)
+        }
+        Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+            visitor.visit_expr_mut(left);
+            visitor.visit_expr_mut(right);
+        }
+        Expr::Call {
+            callee, arguments, ..
+        } => {
+            visitor.visit_expr_mut(callee);
+            arguments
+                .iter_mut()
+                .for_each(|argument| visitor.visit_expr_mut(argument));
+        }
+        Expr::Get { object, .. } => visitor.visit_expr_mut(object),
+        Expr::Set { object, value, .. } => {
+            visitor.visit_expr_mut(object);
+            visitor.visit_expr_mut(value);
+        }
+        Expr::Unary { right, .. } => visitor.visit_expr_mut(right),
+        Expr::Literal { .. } | Expr::Super { .. } | Expr::This { .. } | Expr::Variable { .. } => {}
+    }
+}
+
+pub fn walk_stmt_mut<V: VisitorMut + ?Sized>(visitor: &mut V, stmt: &mut Stmt) {
+    match stmt {
+        Stmt::Block { statements } => statements
+            .iter_mut()
+            .for_each(|statement| visitor.visit_stmt_mut(statement)),
+        Stmt::Class {
+            superclass,
+            methods,
+            ..
+        } => {
+            if let Some(superclass) = superclass {
+                visitor.visit_expr_mut(superclass);
+            }
+
+            methods
+                .iter_mut()
+                .for_each(|method| walk_function_mut(visitor, method));
+        }
+        Stmt::Expression { expression } | Stmt::Print { expression } => {
+            visitor.visit_expr_mut(expression)
+        }
+        Stmt::Function(declaration) => walk_function_mut(visitor, declaration),
+        Stmt::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => {
+            visitor.visit_expr_mut(condition);
+            visitor.visit_stmt_mut(then_branch);
+
+            if let Some(else_branch) = else_branch {
+                visitor.visit_stmt_mut(else_branch);
+            }
+        }
+        Stmt::Return { value, .. } => {
+            if let Some(value) = value {
+                visitor.visit_expr_mut(value);
+            }
+        }
+        Stmt::Var { initializer, .. } => {
+            if let Some(initializer) = initializer {
+                visitor.visit_expr_mut(initializer);
+            }
+        }
+        Stmt::While { condition, body } => {
+            visitor.visit_expr_mut(condition);
+            visitor.visit_stmt_mut(body);
+        }
+    }
+}
+
+// Declarations are shared with closures, so one still shared at rewrite time
+// is copied rather than changed under them
+fn walk_function_mut<V: VisitorMut + ?Sized>(visitor: &mut V, declaration: &mut Rc<FunctionDecl>) {
+    Rc::make_mut(declaration)
+        .body
+        .iter_mut()
+        .for_each(|statement| visitor.visit_stmt_mut(statement));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parser::Parser, scanner::Scanner, token::TokenType};
+
+    use super::*;
+
+    fn parse(src: &str) -> Vec<Stmt> {
+        let tokens = Scanner::new(src.to_string()).scan_tokens().unwrap();
+        Parser::new(&tokens).parse().unwrap()
+    }
+
+    // Folds additions of number literals, children first
+    struct Folder;
+
+    impl VisitorMut for Folder {
+        fn visit_expr_mut(&mut self, expr: &mut Expr) {
+            walk_expr_mut(self, expr);
+
+            if let Expr::Binary {
+                left,
+                operator,
+                right,
+            } = expr
+            {
+                if let (
+                    TokenType::Plus,
+                    Expr::Literal {
+                        value: Literal::Number(a),
+                    },
+                    Expr::Literal {
+                        value: Literal::Number(b),
+                    },
+                ) = (operator.token_type, left.as_ref(), right.as_ref())
+                {
+                    *expr = Expr::Literal {
+                        value: Literal::Number(a + b),
+                    };
+                }
+            }
+        }
+    }
+
+    // Counts variable references, ignoring everything else
+    struct Counter(usize);
+
+    impl VisitorMut for Counter {
+        fn visit_expr_mut(&mut self, expr: &mut Expr) {
+            if let Expr::Variable { .. } = expr {
+                self.0 += 1;
+            }
+
+            walk_expr_mut(self, expr);
+        }
+    }
+
+    // The same count through the default read-only walks
+    impl ExprVisitor<()> for Counter {
+        fn visit_variable(&mut self, _id: ExprId, _name: &Token) {
+            self.0 += 1;
+        }
+    }
+
+    impl StmtVisitor<()> for Counter {
+        fn visit_child_expr(&mut self, expr: &Expr) {
+            self.visit_expr(expr)
+        }
+    }
+
+    #[test]
+    fn check_rewrite() {
+        let mut statements = parse("fun f() { return 1 + 2 + a; } print (1 + 2) * 3;");
+        statements
+            .iter_mut()
+            .for_each(|statement| Folder.visit_stmt_mut(statement));
+
+        let printed: Vec<String> = statements.iter().map(Stmt::print).collect();
+
+        assert_eq!(
+            printed,
+            ["(fun f() (return (+ 3 a)))", "(print (* (group 3) 3))"]
+        );
+    }
+
+    #[test]
+    fn check_walk() {
+        let mut statements = parse("class A < B { m() { if (a) b = c.d; else while (e) f(g); } }");
+        let mut counter = Counter(0);
+        statements
+            .iter_mut()
+            .for_each(|statement| counter.visit_stmt_mut(statement));
+
+        assert_eq!(counter.0, 6);
+
+        let mut counter = Counter(0);
+        statements
+            .iter()
+            .for_each(|statement| counter.visit_stmt(statement));
+
+        assert_eq!(counter.0, 6);
+    }
+}
@@ -0,0 +1,727 @@
+use std::rc::Rc;
+
+use crate::{
+    ast::{Expr, FunctionDecl, Literal, Stmt},
+    error::{Error::CompileErr, Result},
+    symbol::Symbol,
+    token::{Span, Token, TokenType},
+    vm::{
+        chunk::{Chunk, OpCode},
+        heap::Heap,
+        object::{Obj, ObjFunction, ObjRef},
+        value::Value,
+    },
+};
+
+const MAX_LOCALS: usize = 256;
+const MAX_UPVALUES: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FunctionKind {
+    Script,
+    Function,
+    Method,
+    Initializer,
+}
+
+struct Local {
+    name: Symbol,
+    // `None` until the variable's initializer has been compiled
+    depth: Option<usize>,
+    is_captured: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Upvalue {
+    index: u8,
+    is_local: bool,
+}
+
+struct FunctionState {
+    kind: FunctionKind,
+    name: Option<Symbol>,
+    arity: usize,
+    chunk: Chunk,
+    locals: Vec<Local>,
+    upvalues: Vec<Upvalue>,
+    scope_depth: usize,
+}
+
+impl FunctionState {
+    fn new(kind: FunctionKind, name: Option<Symbol>) -> Self {
+        // Slot zero holds the callee itself, or the receiver inside methods
+        let slot_zero = match kind {
+            FunctionKind::Method | FunctionKind::Initializer => Symbol::THIS,
+            FunctionKind::Script | FunctionKind::Function => Symbol::EMPTY,
+        };
+
+        Self {
+            kind,
+            name,
+            arity: 0,
+            chunk: Chunk::new(),
+            locals: vec![Local {
+                name: slot_zero,
+                depth: Some(0),
+                is_captured: false,
+            }],
+            upvalues: vec![],
+            scope_depth: 0,
+        }
+    }
+}
+
+// Compiles a resolved AST into bytecode. Static errors such as redeclared
+// locals are already reported by the resolver, so only VM limits are checked.
+pub struct Compiler<'a> {
+    heap: &'a mut Heap,
+    states: Vec<FunctionState>,
+    // Position of the token currently being compiled, recorded per byte
+    line: usize,
+    span: Span,
+}
+
+impl<'a> Compiler<'a> {
+    pub fn new(heap: &'a mut Heap) -> Self {
+        Self {
+            heap,
+            states: vec![],
+            line: 1,
+            span: Span::default(),
+        }
+    }
+
+    pub fn compile(mut self, statements: &[Stmt]) -> Result<ObjRef> {
+        self.states
+            .push(FunctionState::new(FunctionKind::Script, None));
+
+        for statement in statements {
+            self.statement(statement)?;
+        }
+
+        let (function, _) = self.end_function();
+
+        Ok(function)
+    }
+
+    fn statement(&mut self, stmt: &Stmt) -> Result<()> {
+        match stmt {
+            Stmt::Block { statements } => {
+                self.begin_scope();
+
+                for statement in statements {
+                    self.statement(statement)?;
+                }
+
+                self.end_scope();
+            }
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+            } => {
+                self.locate(name);
+
+                let name_constant = self.identifier_constant(name.symbol())?;
+                self.declare_variable(name.symbol())?;
+                self.emit_with_index(OpCode::Class, name_constant);
+                self.define_variable(name_constant);
+
+                if let Some(superclass) = superclass {
+                    self.expression(superclass)?;
+
+                    // The superclass stays on the stack as a hidden `super` local
+                    self.begin_scope();
+                    self.add_local(Symbol::SUPER)?;
+                    self.define_variable(0);
+
+                    self.named_variable(name.symbol(), false)?;
+                    self.emit_op(OpCode::Inherit);
+                }
+
+                self.named_variable(name.symbol(), false)?;
+
+                for method in methods {
+                    self.locate(&method.name);
+
+                    let kind = if method.name.symbol() == Symbol::INIT {
+                        FunctionKind::Initializer
+                    } else {
+                        FunctionKind::Method
+                    };
+                    let method_constant = self.identifier_constant(method.name.symbol())?;
+
+                    self.function(method, kind)?;
+                    self.emit_with_index(OpCode::Method, method_constant);
+                }
+
+                self.emit_op(OpCode::Pop);
+
+                if superclass.is_some() {
+                    self.end_scope();
+                }
+            }
+            Stmt::Expression { expression } => {
+                self.expression(expression)?;
+                self.emit_op(OpCode::Pop);
+            }
+            Stmt::Function(declaration) => {
+                self.locate(&declaration.name);
+
+                let global = self.parse_variable(declaration.name.symbol())?;
+                // Mark it initialized up front so the body can refer to itself
+                self.mark_initialized();
+                self.function(declaration, FunctionKind::Function)?;
+                self.define_variable(global);
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expression(condition)?;
+
+                let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit_op(OpCode::Pop);
+                self.statement(then_branch)?;
+
+                let else_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(then_jump)?;
+                self.emit_op(OpCode::Pop);
+
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch)?;
+                }
+
+                self.patch_jump(else_jump)?;
+            }
+            Stmt::Print { expression } => {
+                self.expression(expression)?;
+                self.emit_op(OpCode::Print);
+            }
+            Stmt::Return { keyword, value } => {
+                self.locate(keyword);
+
+                match value {
+                    Some(value) => {
+                        self.expression(value)?;
+                        self.emit_op(OpCode::Return);
+                    }
+                    None => self.emit_return(),
+                }
+            }
+            Stmt::Var { name, initializer } => {
+                self.locate(name);
+
+                let global = self.parse_variable(name.symbol())?;
+
+                match initializer {
+                    Some(initializer) => self.expression(initializer)?,
+                    None => self.emit_op(OpCode::Nil),
+                }
+
+                self.define_variable(global);
+            }
+            Stmt::While { condition, body } => {
+                let loop_start = self.chunk().code.len();
+                self.expression(condition)?;
+
+                let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit_op(OpCode::Pop);
+                self.statement(body)?;
+                self.emit_loop(loop_start)?;
+
+                self.patch_jump(exit_jump)?;
+                self.emit_op(OpCode::Pop);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn expression(&mut self, expr: &Expr) -> Result<()> {
+        match expr {
+            Expr::Assign { name, value, .. } => {
+                self.expression(value)?;
+                self.locate(name);
+                self.named_variable(name.symbol(), true)?;
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                self.expression(left)?;
+                self.expression(right)?;
+                self.locate(operator);
+
+                match operator.token_type {
+                    TokenType::BangEqual => {
+                        self.emit_op(OpCode::Equal);
+                        self.emit_op(OpCode::Not);
+                    }
+                    TokenType::EqualEqual => self.emit_op(OpCode::Equal),
+                    TokenType::Greater => self.emit_op(OpCode::Greater),
+                    TokenType::GreaterEqual => self.emit_op(OpCode::GreaterEqual),
+                    TokenType::Less => self.emit_op(OpCode::Less),
+                    TokenType::LessEqual => self.emit_op(OpCode::LessEqual),
+                    TokenType::Plus => self.emit_op(OpCode::Add),
+                    TokenType::Minus => self.emit_op(OpCode::Subtract),
+                    TokenType::Star => self.emit_op(OpCode::Multiply),
+                    TokenType::Slash => self.emit_op(OpCode::Divide),
+                    ref token_type => {
+                        return Err(self.error(
+                            "E0301",
+                            &format!("Invalid binary operator: {}", token_type),
+                            "no instruction for this operator",
+                        ))
+                    }
+                }
+            }
+            Expr::Call {
+                callee,
+                paren,
+                arguments,
+            } => {
+                self.expression(callee)?;
+
+                for argument in arguments {
+                    self.expression(argument)?;
+                }
+
+                self.locate(paren);
+
+                let arg_count = u8::try_from(arguments.len()).map_err(|_| {
+                    self.error(
+                        "E0307",
+                        "Can't have more than 255 arguments",
+                        "too many arguments for one call",
+                    )
+                })?;
+                self.emit_with_operand(OpCode::Call, arg_count);
+            }
+            Expr::Get { object, name } => {
+                self.expression(object)?;
+                self.locate(name);
+
+                let name_constant = self.identifier_constant(name.symbol())?;
+                self.emit_with_index(OpCode::GetProperty, name_constant);
+            }
+            Expr::Grouping { expression } => self.expression(expression)?,
+            Expr::Literal { value } => match value {
+                Literal::Bool(true) => self.emit_op(OpCode::True),
+                Literal::Bool(false) => self.emit_op(OpCode::False),
+                Literal::None => self.emit_op(OpCode::Nil),
+                Literal::Number(num) => self.emit_constant(Value::Number(*num))?,
+                Literal::Stringy(value) => {
+                    let string = self.heap.intern(value.as_str());
+                    self.emit_constant(Value::Obj(string))?;
+                }
+            },
+            Expr::Logical {
+                left,
+                operator,
+                right,
+            } => {
+                self.expression(left)?;
+
+                // Leave the deciding operand on the stack when short-circuiting
+                if operator.token_type == TokenType::And {
+                    let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+                    self.emit_op(OpCode::Pop);
+                    self.expression(right)?;
+                    self.patch_jump(end_jump)?;
+                } else {
+                    let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                    let end_jump = self.emit_jump(OpCode::Jump);
+                    self.patch_jump(else_jump)?;
+                    self.emit_op(OpCode::Pop);
+                    self.expression(right)?;
+                    self.patch_jump(end_jump)?;
+                }
+            }
+            Expr::Set {
+                object,
+                name,
+                value,
+            } => {
+                self.expression(object)?;
+                self.expression(value)?;
+                self.locate(name);
+
+                let name_constant = self.identifier_constant(name.symbol())?;
+                self.emit_with_index(OpCode::SetProperty, name_constant);
+            }
+            Expr::Super {
+                keyword, method, ..
+            } => {
+                self.locate(keyword);
+
+                let name_constant = self.identifier_constant(method.symbol())?;
+                self.named_variable(Symbol::THIS, false)?;
+                self.named_variable(Symbol::SUPER, false)?;
+                self.emit_with_index(OpCode::GetSuper, name_constant);
+            }
+            Expr::This { keyword, .. } => {
+                self.locate(keyword);
+                self.named_variable(Symbol::THIS, false)?;
+            }
+            Expr::Unary { operator, right } => {
+                self.expression(right)?;
+                self.locate(operator);
+
+                match operator.token_type {
+                    TokenType::Bang => self.emit_op(OpCode::Not),
+                    TokenType::Minus => self.emit_op(OpCode::Negate),
+                    ref token_type => {
+                        return Err(self.error(
+                            "E0301",
+                            &format!("Invalid unary operator: {}", token_type),
+                            "no instruction for this operator",
+                        ))
+                    }
+                }
+            }
+            Expr::Variable { name, .. } => {
+                self.locate(name);
+                self.named_variable(name.symbol(), false)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn function(&mut self, declaration: &FunctionDecl, kind: FunctionKind) -> Result<()> {
+        self.states
+            .push(FunctionState::new(kind, Some(declaration.name.symbol())));
+        self.begin_scope();
+
+        for param in &declaration.params {
+            self.current().arity += 1;
+
+            let constant = self.parse_variable(param.symbol())?;
+            self.define_variable(constant);
+        }
+
+        for statement in &declaration.body {
+            self.statement(statement)?;
+        }
+
+        let (function, upvalues) = self.end_function();
+        self.locate(&declaration.name);
+
+        let constant = self.make_constant(Value::Obj(function))?;
+        self.emit_with_index(OpCode::Closure, constant);
+
+        for upvalue in upvalues {
+            self.emit_byte(upvalue.is_local as u8);
+            self.emit_byte(upvalue.index);
+        }
+
+        Ok(())
+    }
+
+    fn end_function(&mut self) -> (ObjRef, Vec<Upvalue>) {
+        self.emit_return();
+
+        let state = self.states.pop().expect("Function state stack underflow");
+        let function = self.heap.alloc(Obj::Function(ObjFunction {
+            name: state.name,
+            arity: state.arity,
+            upvalue_count: state.upvalues.len(),
+            chunk: Rc::new(state.chunk),
+        }));
+
+        (function, state.upvalues)
+    }
+
+    fn named_variable(&mut self, name: Symbol, assign: bool) -> Result<()> {
+        let current = self.states.len() - 1;
+
+        let (get_op, set_op, operand) = if let Some(slot) = self.resolve_local(current, name) {
+            (OpCode::GetLocal, OpCode::SetLocal, slot)
+        } else if let Some(index) = self.resolve_upvalue(current, name)? {
+            (OpCode::GetUpvalue, OpCode::SetUpvalue, index)
+        } else {
+            let constant = self.identifier_constant(name)?;
+            let op = if assign {
+                OpCode::SetGlobal
+            } else {
+                OpCode::GetGlobal
+            };
+            self.emit_with_index(op, constant);
+
+            return Ok(());
+        };
+
+        self.emit_with_operand(if assign { set_op } else { get_op }, operand);
+
+        Ok(())
+    }
+
+    fn resolve_local(&self, state: usize, name: Symbol) -> Option<u8> {
+        self.states[state]
+            .locals
+            .iter()
+            .rposition(|local| local.name == name)
+            .map(|slot| slot as u8)
+    }
+
+    fn resolve_upvalue(&mut self, state: usize, name: Symbol) -> Result<Option<u8>> {
+        if state == 0 {
+            return Ok(None);
+        }
+
+        if let Some(local) = self.resolve_local(state - 1, name) {
+            self.states[state - 1].locals[local as usize].is_captured = true;
+            return self.add_upvalue(state, local, true).map(Some);
+        }
+
+        match self.resolve_upvalue(state - 1, name)? {
+            Some(index) => self.add_upvalue(state, index, false).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn add_upvalue(&mut self, state: usize, index: u8, is_local: bool) -> Result<u8> {
+        let upvalue = Upvalue { index, is_local };
+        let upvalues = &mut self.states[state].upvalues;
+
+        if let Some(existing) = upvalues.iter().position(|other| *other == upvalue) {
+            return Ok(existing as u8);
+        }
+
+        if upvalues.len() == MAX_UPVALUES {
+            return Err(self.error(
+                "E0302",
+                "Too many closure variables in function",
+                "one captured variable too many",
+            ));
+        }
+
+        upvalues.push(upvalue);
+
+        Ok((upvalues.len() - 1) as u8)
+    }
+
+    // Returns the constant index for globals, or 0 for locals which need none
+    fn parse_variable(&mut self, name: Symbol) -> Result<u16> {
+        self.declare_variable(name)?;
+
+        if self.current().scope_depth > 0 {
+            return Ok(0);
+        }
+
+        self.identifier_constant(name)
+    }
+
+    fn declare_variable(&mut self, name: Symbol) -> Result<()> {
+        if self.current().scope_depth == 0 {
+            return Ok(());
+        }
+
+        self.add_local(name)
+    }
+
+    fn add_local(&mut self, name: Symbol) -> Result<()> {
+        if self.current().locals.len() == MAX_LOCALS {
+            return Err(self.error(
+                "E0303",
+                "Too many local variables in function",
+                "one local variable too many",
+            ));
+        }
+
+        self.current().locals.push(Local {
+            name,
+            depth: None,
+            is_captured: false,
+        });
+
+        Ok(())
+    }
+
+    fn define_variable(&mut self, global: u16) {
+        if self.current().scope_depth > 0 {
+            self.mark_initialized();
+            return;
+        }
+
+        self.emit_with_index(OpCode::DefineGlobal, global);
+    }
+
+    fn mark_initialized(&mut self) {
+        let state = self.current();
+
+        if state.scope_depth == 0 {
+            return;
+        }
+
+        if let Some(local) = state.locals.last_mut() {
+            local.depth = Some(state.scope_depth);
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.current().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        self.current().scope_depth -= 1;
+
+        loop {
+            let state = self.current();
+            let depth = state.scope_depth;
+
+            let captured = match state.locals.last() {
+                Some(local) if local.depth.is_some_and(|local_depth| local_depth > depth) => {
+                    local.is_captured
+                }
+                _ => break,
+            };
+
+            state.locals.pop();
+
+            if captured {
+                self.emit_op(OpCode::CloseUpvalue);
+            } else {
+                self.emit_op(OpCode::Pop);
+            }
+        }
+    }
+
+    fn identifier_constant(&mut self, name: Symbol) -> Result<u16> {
+        let index = self.chunk_mut().add_name(name);
+
+        self.constant_index(index)
+    }
+
+    fn make_constant(&mut self, value: Value) -> Result<u16> {
+        let index = self.chunk_mut().add_constant(value);
+
+        self.constant_index(index)
+    }
+
+    fn constant_index(&self, index: usize) -> Result<u16> {
+        u16::try_from(index).map_err(|_| {
+            self.error(
+                "E0304",
+                "Too many constants in one chunk",
+                "one constant too many",
+            )
+        })
+    }
+
+    fn emit_constant(&mut self, value: Value) -> Result<()> {
+        let constant = self.make_constant(value)?;
+        self.emit_with_index(OpCode::Constant, constant);
+
+        Ok(())
+    }
+
+    fn emit_return(&mut self) {
+        if self.current().kind == FunctionKind::Initializer {
+            self.emit_with_operand(OpCode::GetLocal, 0);
+        } else {
+            self.emit_op(OpCode::Nil);
+        }
+
+        self.emit_op(OpCode::Return);
+    }
+
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.emit_op(op);
+        self.emit_byte(0xff);
+        self.emit_byte(0xff);
+
+        self.chunk().code.len() - 2
+    }
+
+    fn patch_jump(&mut self, offset: usize) -> Result<()> {
+        // -2 to adjust for the jump offset itself
+        let jump = self.chunk().code.len() - offset - 2;
+        let jump = u16::try_from(jump).map_err(|_| {
+            self.error(
+                "E0305",
+                "Too much code to jump over",
+                "this branch is too long",
+            )
+        })?;
+
+        let [high, low] = jump.to_be_bytes();
+        self.chunk_mut().code[offset] = high;
+        self.chunk_mut().code[offset + 1] = low;
+
+        Ok(())
+    }
+
+    fn emit_loop(&mut self, loop_start: usize) -> Result<()> {
+        self.emit_op(OpCode::Loop);
+
+        let offset = self.chunk().code.len() - loop_start + 2;
+        let offset = u16::try_from(offset)
+            .map_err(|_| self.error("E0306", "Loop body too large", "this loop is too long"))?;
+
+        let [high, low] = offset.to_be_bytes();
+        self.emit_byte(high);
+        self.emit_byte(low);
+
+        Ok(())
+    }
+
+    fn emit_with_operand(&mut self, op: OpCode, operand: u8) {
+        self.emit_op(op);
+        self.emit_byte(operand);
+    }
+
+    // Constant and name indices take two bytes, big-endian like jump offsets,
+    // so a chunk can refer to 65536 of each
+    fn emit_with_index(&mut self, op: OpCode, index: u16) {
+        let [high, low] = index.to_be_bytes();
+        self.emit_op(op);
+        self.emit_byte(high);
+        self.emit_byte(low);
+    }
+
+    fn emit_op(&mut self, op: OpCode) {
+        self.emit_byte(op as u8);
+    }
+
+    fn emit_byte(&mut self, byte: u8) {
+        let (line, span) = (self.line, self.span);
+        self.chunk_mut().write(byte, line, span);
+    }
+
+    fn locate(&mut self, token: &Token) {
+        self.line = token.line;
+        self.span = token.span;
+    }
+
+    fn current(&mut self) -> &mut FunctionState {
+        self.states
+            .last_mut()
+            .expect("No function is being compiled")
+    }
+
+    fn chunk(&self) -> &Chunk {
+        &self
+            .states
+            .last()
+            .expect("No function is being compiled")
+            .chunk
+    }
+
+    fn chunk_mut(&mut self) -> &mut Chunk {
+        &mut self.current().chunk
+    }
+
+    fn error(&self, code: &'static str, message: &str, label: &str) -> crate::error::Error {
+        CompileErr {
+            line: self.line,
+            span: self.span,
+            code,
+            message: message.to_string(),
+            label: label.to_string(),
+        }
+    }
+}
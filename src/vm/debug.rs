@@ -0,0 +1,261 @@
+use std::fmt::Write;
+
+use crate::vm::{
+    chunk::{Chunk, OpCode},
+    heap::Heap,
+    object::{Obj, ObjRef},
+    value::Value,
+};
+
+// Disassembles `function` followed by every function nested inside it
+pub fn disassemble_function(heap: &Heap, function: ObjRef) -> String {
+    let mut buf = String::new();
+    let mut pending = vec![function];
+
+    while let Some(function) = pending.pop() {
+        let function = heap.function(function);
+        let name = function.name.map_or("<script>", |name| name.as_str());
+
+        if !buf.is_empty() {
+            buf.push('\n');
+        }
+
+        buf.push_str(&disassemble_chunk(heap, &function.chunk, name));
+
+        // Reversed so nested functions are listed in source order
+        function.chunk.constants.iter().rev().for_each(|constant| {
+            if let Value::Obj(obj) = constant {
+                if let Obj::Function(_) = heap.get(*obj) {
+                    pending.push(*obj);
+                }
+            }
+        });
+    }
+
+    buf
+}
+
+pub fn disassemble_chunk(heap: &Heap, chunk: &Chunk, name: &str) -> String {
+    let mut buf = format!("== {} ==\n", name);
+    let mut offset = 0;
+
+    while offset < chunk.code.len() {
+        offset = disassemble_instruction(heap, chunk, offset, &mut buf);
+    }
+
+    buf
+}
+
+// Appends one instruction to `buf` and returns the offset of the next one
+pub fn disassemble_instruction(
+    heap: &Heap,
+    chunk: &Chunk,
+    offset: usize,
+    buf: &mut String,
+) -> usize {
+    let _ = write!(buf, "{:04} ", offset);
+
+    if offset > 0 && chunk.lines[offset] == chunk.lines[offset - 1] {
+        buf.push_str("   | ");
+    } else {
+        let _ = write!(buf, "{:4} ", chunk.lines[offset]);
+    }
+
+    let byte = chunk.code[offset];
+    let Some(op) = OpCode::from_byte(byte) else {
+        let _ = writeln!(buf, "Unknown opcode {}", byte);
+        return offset + 1;
+    };
+
+    match op {
+        OpCode::Constant => constant_instruction(heap, chunk, op, offset, buf),
+        OpCode::GetGlobal
+        | OpCode::DefineGlobal
+        | OpCode::SetGlobal
+        | OpCode::GetProperty
+        | OpCode::SetProperty
+        | OpCode::GetSuper
+        | OpCode::Class
+        | OpCode::Method => name_instruction(chunk, op, offset, buf),
+        OpCode::GetLocal
+        | OpCode::SetLocal
+        | OpCode::GetUpvalue
+        | OpCode::SetUpvalue
+        | OpCode::Call => byte_instruction(chunk, op, offset, buf),
+        OpCode::Jump | OpCode::JumpIfFalse => jump_instruction(chunk, op, 1, offset, buf),
+        OpCode::Loop => jump_instruction(chunk, op, -1, offset, buf),
+        OpCode::Closure => closure_instruction(heap, chunk, offset, buf),
+        _ => {
+            let _ = writeln!(buf, "{}", op_name(op));
+            offset + 1
+        }
+    }
+}
+
+fn constant_instruction(
+    heap: &Heap,
+    chunk: &Chunk,
+    op: OpCode,
+    offset: usize,
+    buf: &mut String,
+) -> usize {
+    let constant = read_index(chunk, offset);
+    let value = heap.format_value(chunk.constants[constant]);
+    let _ = writeln!(buf, "{:<16} {:4} '{}'", op_name(op), constant, value);
+
+    offset + 3
+}
+
+fn name_instruction(chunk: &Chunk, op: OpCode, offset: usize, buf: &mut String) -> usize {
+    let name = read_index(chunk, offset);
+    let _ = writeln!(
+        buf,
+        "{:<16} {:4} '{}'",
+        op_name(op),
+        name,
+        chunk.names[name]
+    );
+
+    offset + 3
+}
+
+fn byte_instruction(chunk: &Chunk, op: OpCode, offset: usize, buf: &mut String) -> usize {
+    let slot = chunk.code[offset + 1];
+    let _ = writeln!(buf, "{:<16} {:4}", op_name(op), slot);
+
+    offset + 2
+}
+
+fn jump_instruction(
+    chunk: &Chunk,
+    op: OpCode,
+    sign: isize,
+    offset: usize,
+    buf: &mut String,
+) -> usize {
+    let jump = u16::from_be_bytes([chunk.code[offset + 1], chunk.code[offset + 2]]) as isize;
+    let target = offset as isize + 3 + sign * jump;
+    let _ = writeln!(buf, "{:<16} {:4} -> {}", op_name(op), offset, target);
+
+    offset + 3
+}
+
+// Constant and name operands are two bytes following the opcode
+fn read_index(chunk: &Chunk, offset: usize) -> usize {
+    u16::from_be_bytes([chunk.code[offset + 1], chunk.code[offset + 2]]) as usize
+}
+
+fn closure_instruction(heap: &Heap, chunk: &Chunk, offset: usize, buf: &mut String) -> usize {
+    let constant = read_index(chunk, offset);
+    let value = chunk.constants[constant];
+    let _ = writeln!(
+        buf,
+        "{:<16} {:4} {}",
+        op_name(OpCode::Closure),
+        constant,
+        heap.format_value(value)
+    );
+
+    let Value::Obj(function) = value else {
+        unreachable!("Closure operands are always functions");
+    };
+
+    let mut offset = offset + 3;
+
+    for _ in 0..heap.function(function).upvalue_count {
+        let is_local = chunk.code[offset];
+        let index = chunk.code[offset + 1];
+        let kind = if is_local == 1 { "local" } else { "upvalue" };
+        let _ = writeln!(
+            buf,
+            "{:04}      |                     {} {}",
+            offset, kind, index
+        );
+
+        offset += 2;
+    }
+
+    offset
+}
+
+// Renders `GetLocal` as `OP_GET_LOCAL`, matching clox's naming
+fn op_name(op: OpCode) -> String {
+    let mut name = "OP".to_string();
+
+    format!("{:?}", op).chars().for_each(|c| {
+        if c.is_ascii_uppercase() {
+            name.push('_');
+        }
+
+        name.push(c.to_ascii_uppercase());
+    });
+
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parser::Parser, scanner::Scanner, vm::compiler::Compiler};
+
+    use super::*;
+
+    fn disassemble(src: &str) -> String {
+        let tokens = Scanner::new(src.to_string()).scan_tokens().unwrap();
+        let statements = Parser::new(&tokens).parse().unwrap();
+
+        let mut heap = Heap::new();
+        let function = Compiler::new(&mut heap).compile(&statements).unwrap();
+
+        disassemble_function(&heap, function)
+    }
+
+    #[test]
+    fn check_disassemble() {
+        assert_eq!(
+            disassemble("print 1 + 2;\nvar a = \"hi\";"),
+            "== <script> ==
+0000    1 OP_CONSTANT         0 '1'
+0003    | OP_CONSTANT         1 '2'
+0006    | OP_ADD
+0007    | OP_PRINT
+0008    2 OP_CONSTANT         2 'hi'
+0011    | OP_DEFINE_GLOBAL    0 'a'
+0014    | OP_NIL
+0015    | OP_RETURN
+"
+        );
+    }
+
+    #[test]
+    fn check_disassemble_closures() {
+        assert_eq!(
+            disassemble("fun outer(x) {\nfun inner() { return x; }\nwhile (x) x = nil;\n}"),
+            "== <script> ==
+0000    1 OP_CLOSURE          0 <fn outer>
+0003    | OP_DEFINE_GLOBAL    0 'outer'
+0006    | OP_NIL
+0007    | OP_RETURN
+
+== outer ==
+0000    2 OP_CLOSURE          0 <fn inner>
+0003      |                     local 1
+0005    3 OP_GET_LOCAL        1
+0007    | OP_JUMP_IF_FALSE    7 -> 18
+0010    | OP_POP
+0011    | OP_NIL
+0012    | OP_SET_LOCAL        1
+0014    | OP_POP
+0015    | OP_LOOP            15 -> 5
+0018    | OP_POP
+0019    | OP_NIL
+0020    | OP_RETURN
+
+== inner ==
+0000    2 OP_GET_UPVALUE      0
+0002    | OP_RETURN
+0003    | OP_NIL
+0004    | OP_RETURN
+"
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::vm::{
+    object::{Obj, ObjClass, ObjClosure, ObjFunction, ObjRef},
+    value::Value,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct GcConfig {
+    // Bytes that may be allocated before the first collection
+    pub initial_threshold: usize,
+    // After a collection the next threshold is the live size times this
+    pub growth_factor: usize,
+    // Collect before every allocation to shake out missing roots
+    pub stress: bool,
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        Self {
+            initial_threshold: 1024 * 1024,
+            growth_factor: 2,
+            stress: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Slot {
+    obj: Obj,
+    marked: bool,
+    // Size charged at allocation, since objects like instances grow in place
+    size: usize,
+}
+
+// Arena of VM objects reclaimed by a tracing mark-and-sweep collector. The
+// heap doesn't know the VM's roots, so the VM marks them before `collect`.
+#[derive(Debug, Default)]
+pub struct Heap {
+    slots: Vec<Option<Slot>>,
+    free_slots: Vec<usize>,
+    gray: Vec<ObjRef>,
+    // Every live string, sharing its text with the object. Entries don't keep
+    // their strings alive; a collection drops the ones it frees.
+    strings: HashMap<Rc<str>, ObjRef>,
+    bytes_allocated: usize,
+    next_gc: usize,
+    config: GcConfig,
+}
+
+impl Heap {
+    pub fn new() -> Self {
+        Self::with_config(GcConfig::default())
+    }
+
+    pub fn with_config(config: GcConfig) -> Self {
+        Self {
+            next_gc: config.initial_threshold,
+            config,
+            ..Default::default()
+        }
+    }
+
+    pub fn alloc(&mut self, obj: Obj) -> ObjRef {
+        let size = obj.size();
+        self.bytes_allocated += size;
+
+        let slot = Some(Slot {
+            obj,
+            marked: false,
+            size,
+        });
+
+        match self.free_slots.pop() {
+            Some(index) => {
+                self.slots[index] = slot;
+                ObjRef(index)
+            }
+            None => {
+                self.slots.push(slot);
+                ObjRef(self.slots.len() - 1)
+            }
+        }
+    }
+
+    // Returns the one string object holding `value`, allocating it if needed
+    pub fn intern(&mut self, value: &str) -> ObjRef {
+        if let Some(string) = self.strings.get(value) {
+            return *string;
+        }
+
+        let value: Rc<str> = value.into();
+        let string = self.alloc(Obj::String(value.clone()));
+        self.strings.insert(value, string);
+
+        string
+    }
+
+    pub fn should_collect(&self) -> bool {
+        self.config.stress || self.bytes_allocated > self.next_gc
+    }
+
+    pub fn live_objects(&self) -> usize {
+        self.slots.iter().filter(|slot| slot.is_some()).count()
+    }
+
+    pub fn mark_value(&mut self, value: Value) {
+        if let Value::Obj(obj) = value {
+            self.mark_object(obj);
+        }
+    }
+
+    pub fn mark_object(&mut self, obj: ObjRef) {
+        let slot = self.slot_mut(obj);
+
+        if !slot.marked {
+            slot.marked = true;
+            self.gray.push(obj);
+        }
+    }
+
+    // Traces from the already-marked roots, then frees everything unmarked
+    pub fn collect(&mut self) {
+        while let Some(obj) = self.gray.pop() {
+            let mut children = vec![];
+            self.get(obj).trace(|value| children.push(value));
+
+            children
+                .into_iter()
+                .for_each(|child| self.mark_value(child));
+        }
+
+        let slots = &self.slots;
+        self.strings
+            .retain(|_, string| slots[string.0].as_ref().is_some_and(|slot| slot.marked));
+
+        self.sweep();
+        self.next_gc =
+            (self.bytes_allocated * self.config.growth_factor).max(self.config.initial_threshold);
+    }
+
+    fn sweep(&mut self) {
+        for (index, entry) in self.slots.iter_mut().enumerate() {
+            match entry {
+                Some(slot) if slot.marked => slot.marked = false,
+                Some(slot) => {
+                    self.bytes_allocated -= slot.size;
+                    *entry = None;
+                    self.free_slots.push(index);
+                }
+                None => {}
+            }
+        }
+    }
+
+    pub fn get(&self, obj: ObjRef) -> &Obj {
+        &self.slot(obj).obj
+    }
+
+    pub fn get_mut(&mut self, obj: ObjRef) -> &mut Obj {
+        &mut self.slot_mut(obj).obj
+    }
+
+    fn slot(&self, obj: ObjRef) -> &Slot {
+        self.slots[obj.0]
+            .as_ref()
+            .expect("Object was used after being collected")
+    }
+
+    fn slot_mut(&mut self, obj: ObjRef) -> &mut Slot {
+        self.slots[obj.0]
+            .as_mut()
+            .expect("Object was used after being collected")
+    }
+
+    pub fn string(&self, obj: ObjRef) -> Option<&str> {
+        match self.get(obj) {
+            Obj::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn function(&self, obj: ObjRef) -> &ObjFunction {
+        match self.get(obj) {
+            Obj::Function(function) => function,
+            obj => unreachable!("Expected a function but found {:?}", obj),
+        }
+    }
+
+    pub fn closure(&self, obj: ObjRef) -> &ObjClosure {
+        match self.get(obj) {
+            Obj::Closure(closure) => closure,
+            obj => unreachable!("Expected a closure but found {:?}", obj),
+        }
+    }
+
+    pub fn class(&self, obj: ObjRef) -> &ObjClass {
+        match self.get(obj) {
+            Obj::Class(class) => class,
+            obj => unreachable!("Expected a class but found {:?}", obj),
+        }
+    }
+
+    // Mirrors `Display for value::Value` so both backends print identically
+    pub fn format_value(&self, value: Value) -> String {
+        match value {
+            Value::Nil => "nil".to_string(),
+            Value::Bool(value) => value.to_string(),
+            Value::Number(value) => value.to_string(),
+            Value::Obj(obj) => self.format_obj(obj),
+        }
+    }
+
+    fn format_obj(&self, obj: ObjRef) -> String {
+        match self.get(obj) {
+            Obj::String(value) => value.to_string(),
+            Obj::Function(function) => match &function.name {
+                Some(name) => format!("<fn {}>", name),
+                None => "<script>".to_string(),
+            },
+            Obj::Native(_) => "<native fn>".to_string(),
+            Obj::Closure(closure) => self.format_obj(closure.function),
+            Obj::Upvalue(_) => "upvalue".to_string(),
+            Obj::Class(class) => class.name.to_string(),
+            Obj::Instance(instance) => match self.get(instance.class) {
+                Obj::Class(class) => format!("{} instance", class.name),
+                _ => unreachable!("Instances always point at a class"),
+            },
+            Obj::BoundMethod(bound) => self.format_obj(bound.method),
+        }
+    }
+}
@@ -0,0 +1,1060 @@
+use std::{
+    collections::HashMap,
+    io::{stdout, Write},
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    ast::Stmt,
+    error::{
+        Error::{self, EvalErr, GeneralErr},
+        Result,
+    },
+    symbol::Symbol,
+    token::Span,
+    vm::{
+        chunk::{Chunk, OpCode},
+        compiler::Compiler,
+        debug::disassemble_function,
+        heap::{GcConfig, Heap},
+        object::{
+            Obj, ObjBoundMethod, ObjClass, ObjClosure, ObjInstance, ObjNative, ObjRef, ObjUpvalue,
+        },
+        value::Value,
+    },
+};
+
+const FRAMES_MAX: usize = 1024;
+
+struct CallFrame {
+    closure: ObjRef,
+    chunk: Rc<Chunk>,
+    ip: usize,
+    // Index of the frame's slot zero on the value stack
+    slots: usize,
+}
+
+pub struct Vm {
+    heap: Heap,
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    globals: HashMap<Symbol, Value>,
+    // Sorted by stack slot so closing a scope only has to look at the tail
+    open_upvalues: Vec<ObjRef>,
+    args: Vec<String>,
+    output: Box<dyn Write>,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self::with_output(Box::new(stdout()))
+    }
+
+    pub fn with_output(output: Box<dyn Write>) -> Self {
+        Self::with_config(output, GcConfig::default())
+    }
+
+    pub fn with_config(output: Box<dyn Write>, gc_config: GcConfig) -> Self {
+        let mut vm = Self {
+            heap: Heap::with_config(gc_config),
+            stack: vec![],
+            frames: vec![],
+            globals: HashMap::new(),
+            open_upvalues: vec![],
+            args: vec![],
+            output,
+        };
+
+        vm.define_native("clock", 0, |_, _| {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_err(|err| GeneralErr {
+                    code: "E0501",
+                    message: format!("Clock went backwards: {}", err),
+                })?;
+
+            Ok(Value::Number(now.as_secs_f64()))
+        });
+        vm.define_native("argc", 0, |vm, _| Ok(Value::Number(vm.args.len() as f64)));
+        vm.define_native("arg", 1, |vm, arguments| {
+            let arg = match arguments[0] {
+                Value::Number(index) if index >= 0.0 && index.fract() == 0.0 => {
+                    vm.args.get(index as usize).cloned()
+                }
+                _ => None,
+            };
+
+            Ok(match arg {
+                Some(arg) => Value::Obj(vm.intern(&arg)),
+                None => Value::Nil,
+            })
+        });
+
+        vm
+    }
+
+    // Arguments given to the script after its path, exposed through `arg(i)`
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.args = args;
+    }
+
+    pub fn interpret(&mut self, statements: &[Stmt]) -> Result<()> {
+        let function = Compiler::new(&mut self.heap).compile(statements)?;
+
+        // Keep the function on the stack so allocating its closure can't free it
+        self.push(Value::Obj(function));
+        let closure = self.alloc(Obj::Closure(ObjClosure {
+            function,
+            upvalues: vec![],
+        }));
+        self.pop();
+
+        self.push(Value::Obj(closure));
+
+        let result = self.call(closure, 0).and_then(|_| self.run());
+
+        if result.is_err() {
+            self.reset_stack();
+        }
+
+        result
+    }
+
+    // Compiles `statements` and lists the resulting bytecode without running it
+    pub fn disassemble(&mut self, statements: &[Stmt]) -> Result<String> {
+        let function = Compiler::new(&mut self.heap).compile(statements)?;
+
+        Ok(disassemble_function(&self.heap, function))
+    }
+
+    fn define_native(
+        &mut self,
+        name: &'static str,
+        arity: usize,
+        function: fn(&mut Vm, &[Value]) -> Result<Value>,
+    ) {
+        let native = self.alloc(Obj::Native(ObjNative {
+            name,
+            arity,
+            function,
+        }));
+
+        self.globals
+            .insert(Symbol::intern(name), Value::Obj(native));
+    }
+
+    fn run(&mut self) -> Result<()> {
+        loop {
+            let byte = self.read_byte();
+            let op = OpCode::from_byte(byte).expect("Compiler emitted an unknown opcode");
+
+            match op {
+                OpCode::Constant => {
+                    let constant = self.read_constant();
+                    self.push(constant);
+                }
+                OpCode::Nil => self.push(Value::Nil),
+                OpCode::True => self.push(Value::Bool(true)),
+                OpCode::False => self.push(Value::Bool(false)),
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::GetLocal => {
+                    let slot = self.frame().slots + self.read_byte() as usize;
+                    self.push(self.stack[slot]);
+                }
+                OpCode::SetLocal => {
+                    let slot = self.frame().slots + self.read_byte() as usize;
+                    self.stack[slot] = self.peek(0);
+                }
+                OpCode::GetGlobal => {
+                    let name = self.read_name();
+
+                    match self.globals.get(&name) {
+                        Some(value) => self.push(*value),
+                        None => return Err(self.undefined_variable(name)),
+                    }
+                }
+                OpCode::DefineGlobal => {
+                    let name = self.read_name();
+                    let value = self.pop();
+                    self.globals.insert(name, value);
+                }
+                OpCode::SetGlobal => {
+                    let name = self.read_name();
+
+                    if !self.globals.contains_key(&name) {
+                        return Err(self.undefined_variable(name));
+                    }
+
+                    self.globals.insert(name, self.peek(0));
+                }
+                OpCode::GetUpvalue => {
+                    let upvalue = self.frame_upvalue();
+
+                    let value = match self.heap.get(upvalue) {
+                        Obj::Upvalue(ObjUpvalue::Open(slot)) => self.stack[*slot],
+                        Obj::Upvalue(ObjUpvalue::Closed(value)) => *value,
+                        obj => unreachable!("Expected an upvalue but found {:?}", obj),
+                    };
+
+                    self.push(value);
+                }
+                OpCode::SetUpvalue => {
+                    let upvalue = self.frame_upvalue();
+                    let value = self.peek(0);
+
+                    match self.heap.get_mut(upvalue) {
+                        Obj::Upvalue(ObjUpvalue::Open(slot)) => {
+                            let slot = *slot;
+                            self.stack[slot] = value;
+                        }
+                        Obj::Upvalue(closed) => *closed = ObjUpvalue::Closed(value),
+                        obj => unreachable!("Expected an upvalue but found {:?}", obj),
+                    }
+                }
+                OpCode::GetProperty => {
+                    let name = self.read_name();
+
+                    let Some((class, field)) = self
+                        .instance(self.peek(0))
+                        .map(|instance| (instance.class, instance.fields.get(&name).copied()))
+                    else {
+                        return Err(self.runtime_error(
+                            "E0406",
+                            "Only instances have properties",
+                            "not an instance",
+                        ));
+                    };
+
+                    match field {
+                        Some(value) => {
+                            self.pop();
+                            self.push(value);
+                        }
+                        None => self.bind_method(class, name)?,
+                    }
+                }
+                OpCode::SetProperty => {
+                    let name = self.read_name();
+
+                    let Value::Obj(instance) = self.peek(1) else {
+                        return Err(self.runtime_error(
+                            "E0407",
+                            "Only instances have fields",
+                            "not an instance",
+                        ));
+                    };
+
+                    let value = self.peek(0);
+
+                    match self.heap.get_mut(instance) {
+                        Obj::Instance(instance) => {
+                            instance.fields.insert(name, value);
+                        }
+                        _ => {
+                            return Err(self.runtime_error(
+                                "E0407",
+                                "Only instances have fields",
+                                "not an instance",
+                            ))
+                        }
+                    }
+
+                    self.pop();
+                    self.pop();
+                    self.push(value);
+                }
+                OpCode::GetSuper => {
+                    let name = self.read_name();
+                    let Value::Obj(superclass) = self.pop() else {
+                        unreachable!("'super' is always bound to a class");
+                    };
+
+                    self.bind_method(superclass, name)?;
+                }
+                OpCode::Equal => {
+                    let b = self.pop();
+                    let a = self.pop();
+                    self.push(Value::Bool(a == b));
+                }
+                OpCode::Greater => self.binary_number_op(|a, b| Value::Bool(a > b))?,
+                OpCode::GreaterEqual => self.binary_number_op(|a, b| Value::Bool(a >= b))?,
+                OpCode::Less => self.binary_number_op(|a, b| Value::Bool(a < b))?,
+                OpCode::LessEqual => self.binary_number_op(|a, b| Value::Bool(a <= b))?,
+                OpCode::Add => match (self.peek(1), self.peek(0)) {
+                    (Value::Number(_), Value::Number(_)) => {
+                        self.binary_number_op(|a, b| Value::Number(a + b))?
+                    }
+                    (Value::Obj(a), Value::Obj(b)) => {
+                        let concatenated = match (self.heap.string(a), self.heap.string(b)) {
+                            (Some(a), Some(b)) => a.to_string() + b,
+                            _ => return Err(self.add_error()),
+                        };
+
+                        let string = self.intern(&concatenated);
+                        self.pop();
+                        self.pop();
+                        self.push(Value::Obj(string));
+                    }
+                    _ => return Err(self.add_error()),
+                },
+                OpCode::Subtract => self.binary_number_op(|a, b| Value::Number(a - b))?,
+                OpCode::Multiply => self.binary_number_op(|a, b| Value::Number(a * b))?,
+                OpCode::Divide => self.binary_number_op(|a, b| Value::Number(a / b))?,
+                OpCode::Not => {
+                    let value = self.pop();
+                    self.push(Value::Bool(value.is_falsey()));
+                }
+                OpCode::Negate => {
+                    let Value::Number(num) = self.peek(0) else {
+                        return Err(self.runtime_error(
+                            "E0403",
+                            "Operand must be a number",
+                            "expected a number",
+                        ));
+                    };
+
+                    self.pop();
+                    self.push(Value::Number(-num));
+                }
+                OpCode::Print => {
+                    let value = self.pop();
+                    let formatted = self.heap.format_value(value);
+
+                    writeln!(self.output, "{}", formatted).map_err(|err| GeneralErr {
+                        code: "E0502",
+                        message: format!("Failed to write output: {}", err),
+                    })?;
+                }
+                OpCode::Jump => {
+                    let offset = self.read_short();
+                    self.frame_mut().ip += offset;
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = self.read_short();
+
+                    if self.peek(0).is_falsey() {
+                        self.frame_mut().ip += offset;
+                    }
+                }
+                OpCode::Loop => {
+                    let offset = self.read_short();
+                    self.frame_mut().ip -= offset;
+                }
+                OpCode::Call => {
+                    let arg_count = self.read_byte() as usize;
+                    self.call_value(self.peek(arg_count), arg_count)?;
+                }
+                OpCode::Closure => {
+                    let Value::Obj(function) = self.read_constant() else {
+                        unreachable!("Closure operands are always functions");
+                    };
+
+                    let upvalue_count = self.heap.function(function).upvalue_count;
+                    let mut upvalues = Vec::with_capacity(upvalue_count);
+
+                    for _ in 0..upvalue_count {
+                        let is_local = self.read_byte() == 1;
+                        let index = self.read_byte() as usize;
+
+                        let upvalue = if is_local {
+                            self.capture_upvalue(self.frame().slots + index)
+                        } else {
+                            self.heap.closure(self.frame().closure).upvalues[index]
+                        };
+
+                        upvalues.push(upvalue);
+                    }
+
+                    let closure = self.alloc(Obj::Closure(ObjClosure { function, upvalues }));
+                    self.push(Value::Obj(closure));
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                }
+                OpCode::Return => {
+                    let result = self.pop();
+                    let frame = self.frames.pop().expect("Returned without a call frame");
+
+                    self.close_upvalues(frame.slots);
+                    self.stack.truncate(frame.slots);
+
+                    if self.frames.is_empty() {
+                        return Ok(());
+                    }
+
+                    self.push(result);
+                }
+                OpCode::Class => {
+                    let name = self.read_name();
+                    let class = self.alloc(Obj::Class(ObjClass {
+                        name,
+                        methods: HashMap::new(),
+                    }));
+
+                    self.push(Value::Obj(class));
+                }
+                OpCode::Inherit => {
+                    let methods = match self.peek(1) {
+                        Value::Obj(superclass) => match self.heap.get(superclass) {
+                            Obj::Class(superclass) => superclass.methods.clone(),
+                            _ => {
+                                return Err(self.runtime_error(
+                                    "E0408",
+                                    "Superclass must be a class",
+                                    "not a class",
+                                ))
+                            }
+                        },
+                        _ => {
+                            return Err(self.runtime_error(
+                                "E0408",
+                                "Superclass must be a class",
+                                "not a class",
+                            ))
+                        }
+                    };
+
+                    let Value::Obj(subclass) = self.peek(0) else {
+                        unreachable!("Inherit always follows the subclass");
+                    };
+
+                    // Copy-down inheritance: methods are final once the class is built
+                    if let Obj::Class(subclass) = self.heap.get_mut(subclass) {
+                        subclass.methods.extend(methods);
+                    }
+
+                    self.pop();
+                }
+                OpCode::Method => {
+                    let name = self.read_name();
+                    let Value::Obj(method) = self.peek(0) else {
+                        unreachable!("Methods are always closures");
+                    };
+                    let Value::Obj(class) = self.peek(1) else {
+                        unreachable!("Methods are always bound to a class");
+                    };
+
+                    if let Obj::Class(class) = self.heap.get_mut(class) {
+                        class.methods.insert(name, method);
+                    }
+
+                    self.pop();
+                }
+            }
+        }
+    }
+
+    fn alloc(&mut self, obj: Obj) -> ObjRef {
+        if self.heap.should_collect() {
+            self.collect_garbage();
+        }
+
+        self.heap.alloc(obj)
+    }
+
+    fn intern(&mut self, value: &str) -> ObjRef {
+        if self.heap.should_collect() {
+            self.collect_garbage();
+        }
+
+        self.heap.intern(value)
+    }
+
+    fn collect_garbage(&mut self) {
+        self.stack
+            .iter()
+            .for_each(|value| self.heap.mark_value(*value));
+        self.frames
+            .iter()
+            .for_each(|frame| self.heap.mark_object(frame.closure));
+        self.globals
+            .values()
+            .for_each(|value| self.heap.mark_value(*value));
+        self.open_upvalues
+            .iter()
+            .for_each(|upvalue| self.heap.mark_object(*upvalue));
+
+        self.heap.collect();
+    }
+
+    fn call_value(&mut self, callee: Value, arg_count: usize) -> Result<()> {
+        let callee_slot = self.stack.len() - arg_count - 1;
+
+        if let Value::Obj(obj) = callee {
+            match self.heap.get(obj) {
+                Obj::Closure(_) => return self.call(obj, arg_count),
+                Obj::Native(native) => {
+                    let (arity, function) = (native.arity, native.function);
+                    self.check_arity(arity, arg_count)?;
+
+                    // The arguments stay on the stack, rooted, while the native runs
+                    let arguments = self.stack[callee_slot + 1..].to_vec();
+                    let result = function(self, &arguments)?;
+                    self.stack.truncate(callee_slot);
+                    self.push(result);
+
+                    return Ok(());
+                }
+                Obj::Class(class) => {
+                    let initializer = class.methods.get(&Symbol::INIT).copied();
+                    let instance = self.alloc(Obj::Instance(ObjInstance {
+                        class: obj,
+                        fields: HashMap::new(),
+                    }));
+
+                    self.stack[callee_slot] = Value::Obj(instance);
+
+                    return match initializer {
+                        Some(initializer) => self.call(initializer, arg_count),
+                        None => self.check_arity(0, arg_count),
+                    };
+                }
+                Obj::BoundMethod(bound) => {
+                    let (receiver, method) = (bound.receiver, bound.method);
+                    self.stack[callee_slot] = receiver;
+
+                    return self.call(method, arg_count);
+                }
+                _ => {}
+            }
+        }
+
+        Err(self.runtime_error(
+            "E0409",
+            "Can only call functions and classes",
+            "not callable",
+        ))
+    }
+
+    fn call(&mut self, closure: ObjRef, arg_count: usize) -> Result<()> {
+        let function = self.heap.function(self.heap.closure(closure).function);
+        let (arity, chunk) = (function.arity, function.chunk.clone());
+
+        self.check_arity(arity, arg_count)?;
+
+        if self.frames.len() == FRAMES_MAX {
+            return Err(self.runtime_error("E0411", "Stack overflow", "too many nested calls"));
+        }
+
+        self.frames.push(CallFrame {
+            closure,
+            chunk,
+            ip: 0,
+            slots: self.stack.len() - arg_count - 1,
+        });
+
+        Ok(())
+    }
+
+    fn check_arity(&self, arity: usize, arg_count: usize) -> Result<()> {
+        if arity == arg_count {
+            return Ok(());
+        }
+
+        Err(self.runtime_error(
+            "E0410",
+            &format!("Expected {} arguments but got {}", arity, arg_count),
+            "wrong number of arguments",
+        ))
+    }
+
+    // Replaces the receiver on top of the stack with `class`'s method bound to it
+    fn bind_method(&mut self, class: ObjRef, name: Symbol) -> Result<()> {
+        let Some(method) = self.heap.class(class).methods.get(&name).copied() else {
+            return Err(self.runtime_error(
+                "E0402",
+                &format!("Undefined property '{}'", name),
+                "no such property",
+            ));
+        };
+
+        let bound = self.alloc(Obj::BoundMethod(ObjBoundMethod {
+            receiver: self.peek(0),
+            method,
+        }));
+
+        self.pop();
+        self.push(Value::Obj(bound));
+
+        Ok(())
+    }
+
+    fn capture_upvalue(&mut self, slot: usize) -> ObjRef {
+        let existing = self.open_upvalues.iter().copied().find(|upvalue| {
+            matches!(self.heap.get(*upvalue), Obj::Upvalue(ObjUpvalue::Open(open)) if *open == slot)
+        });
+
+        if let Some(upvalue) = existing {
+            return upvalue;
+        }
+
+        let upvalue = self.alloc(Obj::Upvalue(ObjUpvalue::Open(slot)));
+        let position = self
+            .open_upvalues
+            .partition_point(|open| self.open_slot(*open) < slot);
+        self.open_upvalues.insert(position, upvalue);
+
+        upvalue
+    }
+
+    // Moves every upvalue at or above `last` off the stack and into the heap
+    fn close_upvalues(&mut self, last: usize) {
+        while let Some(upvalue) = self.open_upvalues.last().copied() {
+            let slot = self.open_slot(upvalue);
+
+            if slot < last {
+                break;
+            }
+
+            let value = self.stack[slot];
+            *self.heap.get_mut(upvalue) = Obj::Upvalue(ObjUpvalue::Closed(value));
+            self.open_upvalues.pop();
+        }
+    }
+
+    fn open_slot(&self, upvalue: ObjRef) -> usize {
+        match self.heap.get(upvalue) {
+            Obj::Upvalue(ObjUpvalue::Open(slot)) => *slot,
+            obj => unreachable!("Expected an open upvalue but found {:?}", obj),
+        }
+    }
+
+    fn binary_number_op(&mut self, op: fn(f64, f64) -> Value) -> Result<()> {
+        let (Value::Number(a), Value::Number(b)) = (self.peek(1), self.peek(0)) else {
+            return Err(self.runtime_error(
+                "E0404",
+                "Operands must be numbers",
+                "expected two numbers",
+            ));
+        };
+
+        self.pop();
+        self.pop();
+        self.push(op(a, b));
+
+        Ok(())
+    }
+
+    fn instance(&self, value: Value) -> Option<&ObjInstance> {
+        match value {
+            Value::Obj(obj) => match self.heap.get(obj) {
+                Obj::Instance(instance) => Some(instance),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn frame(&self) -> &CallFrame {
+        self.frames.last().expect("No active call frame")
+    }
+
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().expect("No active call frame")
+    }
+
+    fn frame_upvalue(&mut self) -> ObjRef {
+        let index = self.read_byte() as usize;
+
+        self.heap.closure(self.frame().closure).upvalues[index]
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let frame = self.frame_mut();
+        let byte = frame.chunk.code[frame.ip];
+        frame.ip += 1;
+
+        byte
+    }
+
+    fn read_short(&mut self) -> usize {
+        let high = self.read_byte();
+        let low = self.read_byte();
+
+        u16::from_be_bytes([high, low]) as usize
+    }
+
+    fn read_constant(&mut self) -> Value {
+        let index = self.read_short();
+
+        self.frame().chunk.constants[index]
+    }
+
+    fn read_name(&mut self) -> Symbol {
+        let index = self.read_short();
+
+        self.frame().chunk.names[index]
+    }
+
+    fn push(&mut self, value: Value) {
+        self.stack.push(value);
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("Value stack underflow")
+    }
+
+    fn peek(&self, distance: usize) -> Value {
+        self.stack[self.stack.len() - 1 - distance]
+    }
+
+    fn reset_stack(&mut self) {
+        self.stack.clear();
+        self.frames.clear();
+        self.open_upvalues.clear();
+    }
+
+    fn undefined_variable(&self, name: Symbol) -> Error {
+        self.runtime_error(
+            "E0401",
+            &format!("Undefined variable '{}'", name),
+            "not defined",
+        )
+    }
+
+    fn add_error(&self) -> Error {
+        self.runtime_error(
+            "E0405",
+            "Operands must be two numbers or two strings",
+            "can't add these",
+        )
+    }
+
+    fn runtime_error(&self, code: &'static str, message: &str, label: &str) -> Error {
+        let (line, span) = self.frames.last().map_or((0, Span::default()), |frame| {
+            let offset = frame.ip.saturating_sub(1);
+            (frame.chunk.lines[offset], frame.chunk.spans[offset])
+        });
+
+        EvalErr {
+            line,
+            span,
+            code,
+            message: message.to_string(),
+            label: label.to_string(),
+        }
+    }
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ast::Expr, interpreter::Interpreter, parser::Parser, resolver::Resolver, scanner::Scanner,
+        test_util::SharedBuf,
+    };
+
+    use super::*;
+
+    fn parse(src: &str) -> Vec<Stmt> {
+        let tokens = Scanner::new(src.to_string()).scan_tokens().unwrap();
+
+        Parser::new(&tokens).parse().unwrap()
+    }
+
+    // Runs `src` on both backends, asserting they agree before returning
+    fn run(src: &str) -> (String, std::result::Result<(), String>) {
+        let statements = parse(src);
+        let locals = Resolver::new().resolve(&statements).unwrap();
+
+        // Stress mode collects on every allocation, so a missing root shows up
+        // as a use-after-collect panic rather than going unnoticed
+        let vm_buf = SharedBuf::default();
+        let gc_config = GcConfig {
+            stress: true,
+            ..Default::default()
+        };
+        let mut vm = Vm::with_config(Box::new(vm_buf.clone()), gc_config);
+        let vm_result = vm.interpret(&statements).map_err(|err| err.to_string());
+
+        let tree_buf = SharedBuf::default();
+        let mut interpreter = Interpreter::with_output(Box::new(tree_buf.clone()));
+        interpreter.resolve(locals);
+        let tree_result = interpreter
+            .interpret(&statements)
+            .map_err(|err| err.to_string());
+
+        assert_eq!(vm_buf.contents(), tree_buf.contents());
+        assert_eq!(vm_result, tree_result);
+
+        (vm_buf.contents(), vm_result)
+    }
+
+    #[test]
+    fn check_expressions_and_globals() {
+        let (output, result) = run("var a = 1;
+            var b;
+            print (a + 2) * 3 - 4 / 2;
+            print \"foo\" + \"bar\" == \"foobar\";
+            print !nil == !false;
+            print b;
+            a = b = 0 / 0;
+            print a >= 1;
+            print a <= 1;");
+
+        assert!(result.is_ok());
+        assert_eq!(output, "7\ntrue\ntrue\nnil\nfalse\nfalse\n");
+    }
+
+    #[test]
+    fn check_control_flow_and_closures() {
+        let (output, result) = run("fun makeCounter() {
+                var i = 0;
+                fun count() {
+                    i = i + 1;
+                    return i;
+                }
+                return count;
+            }
+            var counter = makeCounter();
+            counter();
+            print counter();
+
+            var closures = nil;
+            for (var i = 0; i < 3; i = i + 1) {
+                var captured = i;
+                fun get() { return captured; }
+                if (i == 1) closures = get;
+            }
+            print closures();
+            print nil or \"yes\";
+            print 0 and \"zero is truthy\";
+
+            fun fib(n) {
+                if (n < 2) return n;
+                return fib(n - 1) + fib(n - 2);
+            }
+            print fib(15);
+            print fib;
+            print clock;");
+
+        assert!(result.is_ok());
+        assert_eq!(
+            output,
+            "2\n1\nyes\nzero is truthy\n610\n<fn fib>\n<native fn>\n"
+        );
+    }
+
+    #[test]
+    fn check_classes() {
+        let (output, result) = run("class A {
+                init(name) {
+                    this.name = name;
+                }
+
+                greet() {
+                    return \"A says hi to \" + this.name;
+                }
+
+                kind() {
+                    return \"A\";
+                }
+            }
+            class B < A {
+                greet() {
+                    return super.greet() + \" via \" + this.kind();
+                }
+            }
+            class C < B {
+                kind() {
+                    return \"C\";
+                }
+            }
+            var c = C(\"bob\");
+            print c.greet();
+            print C;
+            print c;
+            print c.greet;
+            print c.init(\"alice\") == c;
+            c.name = \"eve\";
+            print c.greet();");
+
+        assert!(result.is_ok());
+        assert_eq!(
+            output,
+            "A says hi to bob via C\nC\nC instance\n<fn greet>\ntrue\nA says hi to eve via C\n"
+        );
+    }
+
+    #[test]
+    fn check_runtime_errors() {
+        let cases = [
+            ("print -\"a\";", "Operand must be a number"),
+            ("print 1 < \"a\";", "Operands must be numbers"),
+            (
+                "print 1 + nil;",
+                "Operands must be two numbers or two strings",
+            ),
+            ("print missing;", "Undefined variable 'missing'"),
+            ("missing = 1;", "Undefined variable 'missing'"),
+            ("\"str\"();", "Can only call functions and classes"),
+            ("fun f(a) {} f();", "Expected 1 arguments but got 0"),
+            ("class A {} A(1);", "Expected 0 arguments but got 1"),
+            ("class A {} print A().nope;", "Undefined property 'nope'"),
+            ("print 1.field;", "Only instances have properties"),
+            ("var x = 1; x.field = 2;", "Only instances have fields"),
+            ("var A = 1; class B < A {}", "Superclass must be a class"),
+        ];
+
+        for (src, message) in cases {
+            let (_, result) = run(&format!("print \"before\";\n{}", src));
+            assert_eq!(result, Err(format!("[line 2] Runtime error: {}", message)));
+        }
+    }
+
+    #[test]
+    fn check_superclass_span() {
+        let src = "var NotAClass = 1;\nclass A < NotAClass {}";
+        let statements = parse(src);
+        let locals = Resolver::new().resolve(&statements).unwrap();
+
+        let vm_err = Vm::with_output(Box::new(SharedBuf::default()))
+            .interpret(&statements)
+            .unwrap_err();
+
+        let mut interpreter = Interpreter::with_output(Box::new(SharedBuf::default()));
+        interpreter.resolve(locals);
+        let tree_err = interpreter.interpret(&statements).unwrap_err();
+
+        // Both underline the superclass rather than the class being declared
+        for err in [vm_err, tree_err] {
+            let span = err.diagnostic().labels[0].span;
+            assert_eq!(&src[span.start..span.end], "NotAClass");
+            assert_eq!(span.start, 29);
+        }
+    }
+
+    #[test]
+    fn check_stack_overflow() {
+        // The tree-walker recurses natively for each Lox call, so give it room
+        // to reach the frame limit as `main` does
+        let (_, result) = std::thread::Builder::new()
+            .stack_size(64 * 1024 * 1024)
+            .spawn(|| run("fun f() { f(); } f();"))
+            .unwrap()
+            .join()
+            .unwrap();
+
+        assert_eq!(
+            result,
+            Err("[line 1] Runtime error: Stack overflow".to_string())
+        );
+    }
+
+    #[test]
+    fn check_many_constants() {
+        // Past 256 of each, constant and global indices need both operand bytes
+        let src: String = (0..300)
+            .map(|i| format!("var g{} = {};\n", i, i))
+            .chain(["print g0 + g255 + g299;".to_string()])
+            .collect();
+        let (output, result) = run(&src);
+
+        assert!(result.is_ok());
+        assert_eq!(output, "554\n");
+    }
+
+    #[test]
+    fn check_too_many_arguments() {
+        let src = format!("f({});", vec!["0"; 255].join(", "));
+        let mut statements = parse(&src);
+
+        // The parser already refuses a 256th argument, so add it afterwards
+        let Stmt::Expression {
+            expression: Expr::Call { arguments, .. },
+        } = &mut statements[0]
+        else {
+            panic!("Expected a call");
+        };
+        arguments.push(arguments[0].clone());
+
+        let Err(err) = Vm::with_output(Box::new(SharedBuf::default())).interpret(&statements)
+        else {
+            panic!("Expected a compile error");
+        };
+        assert_eq!(
+            err.to_string(),
+            "[line 1] Compile error: Can't have more than 255 arguments"
+        );
+    }
+
+    #[test]
+    fn check_garbage_collection() {
+        let gc_config = GcConfig {
+            initial_threshold: 4096,
+            ..Default::default()
+        };
+        let buf = SharedBuf::default();
+        let mut vm = Vm::with_config(Box::new(buf.clone()), gc_config);
+
+        // Each iteration leaves a self-referencing instance and a string behind
+        let result = vm.interpret(&parse(
+            "class Node {}
+            var kept = Node();
+            kept.name = \"kept\";
+            for (var i = 0; i < 10000; i = i + 1) {
+                var node = Node();
+                node.next = node;
+                node.label = \"node\" + \"!\";
+            }
+            print kept.name;",
+        ));
+
+        assert!(result.is_ok());
+        assert_eq!(buf.contents(), "kept\n");
+        assert!(vm.heap.live_objects() < 1000);
+    }
+
+    #[test]
+    fn check_string_interning() {
+        let mut heap = Heap::new();
+        let kept = heap.intern("kept");
+        heap.intern("dropped");
+
+        assert_eq!(heap.intern(&("ke".to_string() + "pt")), kept);
+        assert_eq!(heap.live_objects(), 2);
+
+        // The table doesn't root its strings, and forgets the ones collected
+        heap.mark_object(kept);
+        heap.collect();
+        assert_eq!(heap.live_objects(), 1);
+        assert_eq!(heap.intern("kept"), kept);
+
+        heap.intern("dropped");
+        assert_eq!(heap.live_objects(), 2);
+    }
+
+    #[test]
+    fn check_script_args() {
+        let statements = parse(
+            "for (var i = 0; i < argc(); i = i + 1) print arg(i);
+            print arg(-1);
+            print arg(2);
+            print arg(\"0\");",
+        );
+        let args = vec!["first".to_string(), "second arg".to_string()];
+        let expected = "first\nsecond arg\nnil\nnil\nnil\n";
+
+        let vm_buf = SharedBuf::default();
+        let mut vm = Vm::with_output(Box::new(vm_buf.clone()));
+        vm.set_args(args.clone());
+        assert!(vm.interpret(&statements).is_ok());
+        assert_eq!(vm_buf.contents(), expected);
+
+        let tree_buf = SharedBuf::default();
+        let mut interpreter = Interpreter::with_output(Box::new(tree_buf.clone()));
+        interpreter.set_args(args);
+        interpreter.resolve(Resolver::new().resolve(&statements).unwrap());
+        assert!(interpreter.interpret(&statements).is_ok());
+        assert_eq!(tree_buf.contents(), expected);
+    }
+}
@@ -0,0 +1,9 @@
+pub mod chunk;
+pub mod compiler;
+pub mod debug;
+pub mod heap;
+pub mod machine;
+pub mod object;
+pub mod value;
+
+pub use machine::Vm;
@@ -0,0 +1,120 @@
+use std::{collections::HashMap, mem::size_of, rc::Rc};
+
+use crate::{
+    error::Result,
+    symbol::Symbol,
+    vm::{chunk::Chunk, value::Value, Vm},
+};
+
+// Index of a slot in the `Heap`; only valid while the object is reachable
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjRef(pub(super) usize);
+
+#[derive(Debug)]
+pub enum Obj {
+    // Only made through `Heap::intern`, so equal strings are the same object
+    String(Rc<str>),
+    Function(ObjFunction),
+    Native(ObjNative),
+    Closure(ObjClosure),
+    Upvalue(ObjUpvalue),
+    Class(ObjClass),
+    Instance(ObjInstance),
+    BoundMethod(ObjBoundMethod),
+}
+
+#[derive(Debug)]
+pub struct ObjFunction {
+    pub name: Option<Symbol>,
+    pub arity: usize,
+    pub upvalue_count: usize,
+    // Shared with call frames so the VM can read code without borrowing the heap
+    pub chunk: Rc<Chunk>,
+}
+
+#[derive(Debug)]
+pub struct ObjNative {
+    pub name: &'static str,
+    pub arity: usize,
+    pub function: fn(&mut Vm, &[Value]) -> Result<Value>,
+}
+
+#[derive(Debug)]
+pub struct ObjClosure {
+    pub function: ObjRef,
+    pub upvalues: Vec<ObjRef>,
+}
+
+// Open upvalues point at a live stack slot until the variable goes out of scope
+#[derive(Debug)]
+pub enum ObjUpvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+#[derive(Debug)]
+pub struct ObjClass {
+    pub name: Symbol,
+    pub methods: HashMap<Symbol, ObjRef>,
+}
+
+#[derive(Debug)]
+pub struct ObjInstance {
+    pub class: ObjRef,
+    pub fields: HashMap<Symbol, Value>,
+}
+
+#[derive(Debug)]
+pub struct ObjBoundMethod {
+    pub receiver: Value,
+    pub method: ObjRef,
+}
+
+impl Obj {
+    // Rough footprint used to pace the garbage collector, not an exact count
+    pub fn size(&self) -> usize {
+        let payload = match self {
+            Obj::String(value) => value.len(),
+            Obj::Function(function) => {
+                function.chunk.code.len()
+                    + function.chunk.lines.len() * size_of::<usize>()
+                    + function.chunk.constants.len() * size_of::<Value>()
+            }
+            Obj::Closure(closure) => closure.upvalues.len() * size_of::<ObjRef>(),
+            Obj::Class(class) => class.methods.len() * size_of::<(Symbol, ObjRef)>(),
+            Obj::Instance(instance) => instance.fields.len() * size_of::<(Symbol, Value)>(),
+            Obj::Native(_) | Obj::Upvalue(_) | Obj::BoundMethod(_) => 0,
+        };
+
+        size_of::<Obj>() + payload
+    }
+
+    // Calls `visit` with every value this object keeps alive
+    pub fn trace(&self, mut visit: impl FnMut(Value)) {
+        match self {
+            Obj::String(_) | Obj::Native(_) => {}
+            Obj::Function(function) => function.chunk.constants.iter().for_each(|v| visit(*v)),
+            Obj::Closure(closure) => {
+                visit(Value::Obj(closure.function));
+                closure
+                    .upvalues
+                    .iter()
+                    .for_each(|upvalue| visit(Value::Obj(*upvalue)));
+            }
+            Obj::Upvalue(ObjUpvalue::Closed(value)) => visit(*value),
+            Obj::Upvalue(ObjUpvalue::Open(_)) => {}
+            Obj::Class(class) => class
+                .methods
+                .values()
+                .for_each(|method| visit(Value::Obj(*method))),
+            Obj::Instance(instance) => {
+                visit(Value::Obj(instance.class));
+                instance.fields.values().for_each(|value| visit(*value));
+            }
+            Obj::BoundMethod(bound) => {
+                visit(bound.receiver);
+                visit(Value::Obj(bound.method));
+            }
+        }
+    }
+}
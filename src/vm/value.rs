@@ -0,0 +1,18 @@
+use crate::vm::object::ObjRef;
+
+// Heap objects are referenced by handle, so values stay `Copy`
+// Strings are interned, so comparing handles compares strings too
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    Obj(ObjRef),
+}
+
+impl Value {
+    // Lox treats only `nil` and `false` as falsey
+    pub fn is_falsey(&self) -> bool {
+        matches!(self, Value::Nil | Value::Bool(false))
+    }
+}